@@ -8,7 +8,36 @@
 import Config
 
 config :global_api,
-  ecto_repos: [GlobalApi.Repo]
+  ecto_repos: [GlobalApi.Repo],
+  # max size of the render cache inside the skins NIF in bytes, 0 disables it
+  render_cache_size: 64 * 1024 * 1024,
+  # what transparent base layer pixels of converted skins are filled with: :default_skin, :nearest_neighbour or :black.
+  # nil doesn't fill them. Filling changes the hashes of skins with a transparent base layer,
+  # so enabling it uploads those skins again
+  base_layer_fill: nil,
+  # the amount of seconds that the exp and nbf of a Bedrock login chain may be off
+  chain_clock_skew: 60,
+  # the base64 encoded DER public keys that Bedrock login chains have to be signed by, nil only trusts Mojang.
+  # They're read when the app starts, call GlobalApi.SkinsNif.reload_trusted_keys after changing them at runtime
+  trusted_chain_keys: nil,
+  # accepts the self-signed chains of offline mode servers as unauthenticated, never enable this in production
+  offline_chains: false,
+  # the max amount of seconds since the identity of a Bedrock login chain was issued, nil only checks exp and nbf.
+  # Clients cache their identity for days, so a max age rejects the logins of players that didn't restart their game
+  chain_max_age: nil,
+  # the OpenID discovery document with the keys of the token logins of newer Bedrock versions, nil disables token logins.
+  # The iss and (when not nil) aud claims of the tokens have to match the issuer and audience
+  token_discovery_url: nil,
+  token_issuer: nil,
+  token_audience: nil,
+  # how many submitted client data signatures are remembered until their login chain expires,
+  # 0 disables replay protection
+  replay_capacity: 100_000,
+  # the hash version of converted skins. Version 1 converts skins like the stored unique skins were converted,
+  # version 2 also ignores pixels that aren't visible in-game.
+  # Unique skins are stored with their hash version and only match hashes of the same version,
+  # so switching versions uploads every skin again unless the stored hashes have been recomputed
+  skin_hash_version: 1
 
 config :sentry,
   environment_name: config_env(),
@@ -7,6 +7,9 @@ defmodule GlobalApi.Application do
   import Cachex.Spec
 
   def start(_type, _args) do
+    # invalid keys would reject every login, so they keep the app from starting
+    :ok = GlobalApi.SkinsNif.reload_trusted_keys()
+
     children = [
       GlobalApi.PromEx,
       {GlobalApi.DatabaseQueue, [pool_size: 7]},
@@ -36,6 +39,7 @@ defmodule GlobalApi.Application do
       GlobalApi.Telemetry,
       create_cache(:project_version),
       GlobalApi.VersionFetcher,
+      GlobalApi.TokenKeyFetcher,
       # Start the Endpoint (http/https)
       GlobalApiWeb.Endpoint
     ]
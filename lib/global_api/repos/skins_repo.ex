@@ -99,8 +99,12 @@ defmodule GlobalApi.SkinsRepo do
     |> Repo.insert(on_conflict: {:replace_all_except, [:inserted_at]}, source: :player_skins)
   end
 
-  def get_unique_skin(hash, is_steve) when is_binary(hash) do
-    Repo.one(from s in UniqueSkin, where: s.hash == ^hash and s.is_steve == ^is_steve, limit: 1)
+  def get_unique_skin(hash, is_steve, hash_version) when is_binary(hash) do
+    Repo.one(
+      from s in UniqueSkin,
+      where: s.hash == ^hash and s.is_steve == ^is_steve and s.hash_version == ^hash_version,
+      limit: 1
+    )
   end
 
   def create_unique_skin(attrs) when is_map(attrs) do
@@ -114,7 +118,7 @@ defmodule GlobalApi.SkinsRepo do
     skin_id = create_unique_skin(attrs).id
     # I don't think that this will ever happen, but just in case
     if skin_id == nil do
-      get_unique_skin(attrs.hash, attrs.is_steve).id
+      get_unique_skin(attrs.hash, attrs.is_steve, attrs.hash_version).id
     else
       skin_id
     end
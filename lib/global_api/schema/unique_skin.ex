@@ -10,13 +10,14 @@ defmodule GlobalApi.UniqueSkin do
     field :value, :string
     field :signature, :string
     field :is_steve, :boolean
+    field :hash_version, :integer, default: 1
     field :inserted_at, :integer
   end
 
   def changeset(skin, attrs) do
     skin
-    |> cast(attrs, [:hash, :texture_id, :value, :signature, :is_steve])
-    |> validate_required([:hash, :texture_id, :value, :signature, :is_steve], message: "cannot add an incomplete skin")
+    |> cast(attrs, [:hash, :texture_id, :value, :signature, :is_steve, :hash_version])
+    |> validate_required([:hash, :texture_id, :value, :signature, :is_steve, :hash_version], message: "cannot add an incomplete skin")
     |> put_change(:inserted_at, System.system_time(:millisecond))
   end
 
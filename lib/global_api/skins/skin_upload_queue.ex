@@ -1,7 +1,9 @@
 defmodule GlobalApi.SkinUploadQueue do
   use GenServer
 
+  alias GlobalApi.SkinsNif
   alias GlobalApi.SkinUploader
+  alias GlobalApi.SocketManager
 
   @type t :: %__MODULE__{
                queue: List.t(),
@@ -21,8 +23,16 @@ defmodule GlobalApi.SkinUploadQueue do
     {:ok, %__MODULE__{}}
   end
 
-  def add_request({_rgba_hash, _is_steve, _png} = data) do
-    GenServer.cast(__MODULE__, {:push, data})
+  def add_request({rgba_hash, is_steve, png}) do
+    # Mojang rejects skins that break its rules, a transparent base layer is made opaque before uploading
+    model = if is_steve, do: :classic, else: :slim
+    case SkinsNif.validate_java_skin(png, model, true) do
+      {[], _fixed_violations, fixed_png} ->
+        GenServer.cast(__MODULE__, {:push, {rgba_hash, is_steve, fixed_png || png}})
+
+      {_violations, _fixed_violations, _fixed_png} ->
+        SocketManager.skin_upload_failed(rgba_hash)
+    end
   end
 
   def resume do
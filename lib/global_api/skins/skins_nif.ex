@@ -1,21 +1,287 @@
 defmodule GlobalApi.SkinsNif do
+  @hash_version Application.compile_env(:global_api, :skin_hash_version, 1)
+
   use Rustler,
       otp_app: :global_api,
-      crate: :skins
+      crate: :skins,
+      load_data: %{
+        # the max size of all cached renders combined in bytes, 0 disables the render cache
+        render_cache_size: Application.compile_env(:global_api, :render_cache_size, 64 * 1024 * 1024),
+        # what transparent base layer pixels of converted skins are filled with, nil disables filling.
+        # filling changes the hashes of skins with a transparent base layer
+        base_layer_fill: Application.compile_env(:global_api, :base_layer_fill, nil),
+        # the amount of seconds that the exp and nbf of a login chain may be off
+        chain_clock_skew: Application.compile_env(:global_api, :chain_clock_skew, 60),
+        # accepts the self-signed chains of offline mode servers as unauthenticated, only meant for testing
+        offline_chains: Application.compile_env(:global_api, :offline_chains, false),
+        # the max amount of seconds since the identity of a login chain was issued, nil only checks exp and nbf
+        chain_max_age: Application.compile_env(:global_api, :chain_max_age, nil),
+        # how many submitted client data signatures are remembered until their login chain expires to reject replays
+        replay_capacity: Application.compile_env(:global_api, :replay_capacity, 100_000),
+        # 1 hashes the converted skin as is, 2 canonicalises it first
+        # so that invisible differences don't matter
+        skin_hash_version: @hash_version
+      }
+
+  @doc "the hash version of the skins converted by validate_and_convert, which is stored with every unique skin"
+  @spec hash_version() :: integer
+  def hash_version, do: @hash_version
 
   @type extra_data() :: {binary, binary, integer}
 
+  @type base_layer_fill() :: :default_skin | :nearest_neighbour | :black
+
+  # authenticated is false when the chain is self-signed, which is only accepted when offline_chains is enabled.
+  # Hashes of a different hash version can't be compared, the skin has to be converted again
+  @type convert_diagnostics() :: %{
+    # nil when filling the base layer is disabled
+    base_layer_fill_policy: base_layer_fill() | nil,
+    filled_base_pixels: integer,
+    authenticated: boolean,
+    hash_version: integer,
+    # compare them with skin_similarity to find near-duplicate skins
+    perceptual_hash: binary
+  }
+
+  # the extra data is nil for self-signed chains, their identity can't be trusted.
+  # replayed is returned for client data that has already been submitted while its login chain is still valid
   @spec validate_and_convert(list, binary) ::
     :invalid_data |
+    :replayed |
+    {:invalid_size | :invalid_geometry, extra_data() | nil} |
+    {:invalid_geometry, binary, extra_data() | nil} |
+    {boolean, binary, binary, binary, extra_data() | nil, convert_diagnostics()}
+  def validate_and_convert(_chain_data, _client_data) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  # validate_and_convert for the token logins of the newer Bedrock versions, the token is validated against the keys
+  # set by set_token_keys and contains the public key that signed the client data
+  @spec validate_token_and_convert(binary, binary, binary, binary | nil) ::
+    :invalid_data |
+    :replayed |
     {:invalid_size | :invalid_geometry, extra_data()} |
     {:invalid_geometry, binary, extra_data()} |
-    {boolean, binary, binary, binary, extra_data()}
-  def validate_and_convert(_chain_data, _client_data) do
+    {boolean, binary, binary, binary, extra_data(), convert_diagnostics()}
+  def validate_token_and_convert(_token, _client_data, _issuer, _audience \\ nil) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  @typedoc "the claims of a login chain and its client data, claims that the client didn't send are nil"
+  @type login_identity() :: %{
+    xuid: binary,
+    display_name: binary,
+    identity: binary,
+    title_id: binary | nil,
+    sandbox_id: binary | nil,
+    identity_public_key: binary,
+    issued_at: integer | nil,
+    device_os: integer | nil,
+    device_model: binary | nil,
+    language_code: binary | nil,
+    game_version: binary | nil,
+    platform_online_id: binary | nil,
+    authenticated: boolean
+  }
+
+  @type chain_error() ::
+    :invalid_length | :malformed_token | :missing_x5u | :key_mismatch | :invalid_public_key | :invalid_signature |
+    :expired | :not_yet_valid | :too_old | :not_certificate_authority | :missing_identity_public_key | :untrusted |
+    :missing_extra_data | :invalid_client_data
+
+  # validates the login chain like validate_and_convert, without converting the skin.
+  # Only validate_and_convert rejects replayed client data, so both can be called for the same login
+  @spec validate_chain([binary], binary) :: {:ok, login_identity()} | {:error, chain_error()}
+  def validate_chain(_chain_data, _client_data) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  # validates many {chain_data, client_data} pairs in parallel, the results are in the same order as the pairs.
+  # Check time can be disabled to verify historical chains, their signatures are still verified
+  @spec validate_chain_batch([{[binary], binary}], boolean) :: [{:ok, login_identity()} | {:error, chain_error()}]
+  def validate_chain_batch(_pairs, _check_time \\ true) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  # replaces the root keys that login chains have to be signed by, every key has to be an ES384 public key.
+  # nil only trusts Mojang
+  @spec set_trusted_keys([binary] | nil) :: :ok | {:error, :missing_keys | :invalid_key}
+  def set_trusted_keys(_keys) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  # trusts the trusted_chain_keys of the current config, so rotating the keys only needs a config change
+  @spec reload_trusted_keys() :: :ok | {:error, :missing_keys | :invalid_key}
+  def reload_trusted_keys do
+    set_trusted_keys(Application.get_env(:global_api, :trusted_chain_keys))
+  end
+
+  # makes the keys of the JWKS document the newest active keys, the previous key set stays active to allow rotation
+  @spec set_token_keys(binary) :: :ok | {:error, :invalid_json | :missing_keys | :invalid_key}
+  def set_token_keys(_jwks) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  @type token_error() ::
+    :malformed_token | :unknown_key | :invalid_signature | :expired | :not_yet_valid | :invalid_issuer | :invalid_audience
+
+  # validates an OpenID Connect token against the keys set by set_token_keys, the claims are decoded like Jason does
+  @spec validate_token(binary, binary, binary | nil) :: {:ok, map} | {:error, token_error()}
+  def validate_token(_token, _issuer, _audience \\ nil) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  @type skin_part() :: :head | :body | :arm_right | :arm_left | :leg_right | :leg_left
+
+  @typedoc "a reason why Mojang wouldn't accept a skin, the pixel count is the amount of base layer pixels that aren't opaque"
+  @type skin_violation() ::
+    :invalid_image |
+    {:invalid_dimensions, integer, integer} |
+    {:file_too_large, integer} |
+    {:transparent_base_layer, skin_part(), integer}
+
+  # auto fix makes the base layer opaque, the violations are those of the fixed skin when it has been fixed.
+  # The fixed violations are the violations of the given skin that the fix resolved
+  @spec validate_java_skin(binary, :classic | :slim, boolean) :: {[skin_violation()], [skin_violation()], binary | nil}
+  def validate_java_skin(_png, _model, _auto_fix \\ false) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  # how alike two skins are based on their perceptual hashes, from 0.0 (nothing alike) to 1.0 (look the same).
+  # The hashes include the colours, so skins that are only recoloured aren't alike
+  @spec skin_similarity(binary, binary) :: float | :invalid_hash
+  def skin_similarity(_perceptual_hash, _other_perceptual_hash) do
     :erlang.nif_error(:nif_not_loaded)
   end
 
-  @spec render_skin_front(binary, :bottom | :top | :both, :classic | :slim, integer) :: :invalid_image | binary
-  def render_skin_front(_data, _layer, _model, _target_width) do
+  # one byte per pixel of a 64x64 skin (row by row), 1 when the pixel is used by the model and 0 otherwise
+  @spec skin_used_area_mask(:classic | :slim) :: binary
+  def skin_used_area_mask(_model) do
     :erlang.nif_error(:nif_not_loaded)
   end
+
+  @type color() :: {byte, byte, byte} | {byte, byte, byte, byte}
+
+  # skins have to be 64x64 or (legacy) 64x32, or a multiple of those up to 1024 pixels wide
+  @type skin_error() :: :invalid_image | :invalid_skin_width | :invalid_skin_height | :skin_too_large
+
+  @type output_format() :: :png | :webp | :gif | :apng
+
+  @type render_options() :: %{
+    optional(:inflate_overlay) => boolean,
+    optional(:shading) => boolean,
+    optional(:ambient_occlusion) => boolean,
+    # a single color, or {top, bottom} for a vertical gradient
+    optional(:background) => color() | {color(), color()} | nil,
+    optional(:drop_shadow) => boolean,
+    optional(:outline) => color() | nil,
+    optional(:format) => output_format()
+  }
+
+  # every render returns :invalid_size when the target width is too small to render anything,
+  # or when it's larger than 2048 pixels
+
+  # renders larger than these widths take more than a millisecond,
+  # so they're rendered on a dirty scheduler instead of blocking a normal scheduler
+  @dirty_flat_width 64
+  @dirty_isometric_width 32
+
+  @spec render_skin_front(binary, :bottom | :top | :both, :classic | :slim, integer, render_options()) :: skin_error() | :invalid_size | binary
+  def render_skin_front(data, layer, model, target_width, options \\ %{}) do
+    if target_width > @dirty_flat_width,
+      do: render_skin_front_dirty(data, layer, model, target_width, options),
+      else: render_skin_front_nif(data, layer, model, target_width, options)
+  end
+
+  @spec render_skin_back(binary, binary | nil, :bottom | :top | :both, :classic | :slim, integer, render_options()) :: skin_error() | :invalid_size | binary
+  def render_skin_back(data, cape_data, layer, model, target_width, options \\ %{}) do
+    if target_width > @dirty_flat_width,
+      do: render_skin_back_dirty(data, cape_data, layer, model, target_width, options),
+      else: render_skin_back_nif(data, cape_data, layer, model, target_width, options)
+  end
+
+  @spec render_skin_isometric(binary, :bottom | :top | :both, :classic | :slim, :idle | :walking | :waving | :arms_out, integer, render_options()) ::
+    skin_error() | :invalid_size | binary
+  def render_skin_isometric(data, layer, model, pose, target_width, options \\ %{}) do
+    if target_width > @dirty_isometric_width,
+      do: render_skin_isometric_dirty(data, layer, model, pose, target_width, options),
+      else: render_skin_isometric_nif(data, layer, model, pose, target_width, options)
+  end
+
+  # skin data is the raw (base64 decoded) SkinData, geometry data and resource patch are the decoded json
+  @spec render_bedrock_skin(binary, integer, integer, binary, binary, :bottom | :top | :both, integer, render_options()) ::
+    :invalid_size | {:invalid_geometry, binary} | binary
+  def render_bedrock_skin(_skin_data, _width, _height, _geometry_data, _resource_patch, _layer, _target_width, _options \\ %{}) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  @spec render_cape(binary, :cape | :elytra, integer, render_options()) :: :invalid_image | :invalid_size | binary
+  def render_cape(data, part, target_width, options \\ %{}) do
+    if target_width > @dirty_flat_width,
+      do: render_cape_dirty(data, part, target_width, options),
+      else: render_cape_nif(data, part, target_width, options)
+  end
+
+  @spec render_profile_card(binary, :classic | :slim, binary, binary | nil, binary, output_format()) :: skin_error() | binary
+  def render_profile_card(_skin_data, _model, _gamertag, _java_name, _xuid, _format \\ :png) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  @typedoc "x offset, y offset, width and height of a skin on the sprite sheet, nil if the skin is an invalid image or has invalid dimensions"
+  @type sprite_position() :: {integer, integer, integer, integer} | nil
+
+  # invalid_size when there are more than 256 skins or the sheet would be larger than 4096x4096 pixels
+  @spec render_sprite_sheet([{binary, :classic | :slim}], integer, :bottom | :top | :both, integer, render_options()) ::
+    {binary, [sprite_position()]} | :invalid_size
+  def render_sprite_sheet(_skins, _columns, _layer, _target_width, _options \\ %{}) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  # the frames are stacked vertically in the image, webp can't contain animations so it's only supported for one frame
+  @spec render_animated_face(binary, integer, integer, :bottom | :top | :both, integer, render_options()) ::
+    :invalid_image | :invalid_size | :unsupported_format | binary
+  def render_animated_face(_data, _frame_count, _frame_delay_ms, _layer, _target_width, _options \\ %{}) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  @spec render_cache_stats() ::
+    %{hits: integer, misses: integer, entries: integer, size: integer, max_size: integer}
+  def render_cache_stats() do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  @typedoc "the arguments of the render NIF with the same name, render_options() are required"
+  @type render_request() ::
+    {:front, binary, :bottom | :top | :both, :classic | :slim, integer, render_options()} |
+    {:back, binary, binary | nil, :bottom | :top | :both, :classic | :slim, integer, render_options()} |
+    {:isometric, binary, :bottom | :top | :both, :classic | :slim, :idle | :walking | :waving | :arms_out, integer, render_options()} |
+    {:cape, binary, :cape | :elytra, integer, render_options()} |
+    {:bedrock, binary, integer, integer, binary, binary, :bottom | :top | :both, integer, render_options()} |
+    {:animated_face, binary, integer, integer, :bottom | :top | :both, integer, render_options()}
+
+  # the results are in the same order as the requests
+  @spec render_batch([render_request()]) ::
+    [skin_error() | :invalid_size | {:invalid_geometry, binary} | binary]
+  def render_batch(_requests) do
+    :erlang.nif_error(:nif_not_loaded)
+  end
+
+  # the variants of the renders above, called based on the target width
+
+  @doc false
+  def render_skin_front_nif(_data, _layer, _model, _target_width, _options), do: :erlang.nif_error(:nif_not_loaded)
+  @doc false
+  def render_skin_front_dirty(_data, _layer, _model, _target_width, _options), do: :erlang.nif_error(:nif_not_loaded)
+  @doc false
+  def render_skin_back_nif(_data, _cape_data, _layer, _model, _target_width, _options), do: :erlang.nif_error(:nif_not_loaded)
+  @doc false
+  def render_skin_back_dirty(_data, _cape_data, _layer, _model, _target_width, _options), do: :erlang.nif_error(:nif_not_loaded)
+  @doc false
+  def render_skin_isometric_nif(_data, _layer, _model, _pose, _target_width, _options), do: :erlang.nif_error(:nif_not_loaded)
+  @doc false
+  def render_skin_isometric_dirty(_data, _layer, _model, _pose, _target_width, _options), do: :erlang.nif_error(:nif_not_loaded)
+  @doc false
+  def render_cape_nif(_data, _part, _target_width, _options), do: :erlang.nif_error(:nif_not_loaded)
+  @doc false
+  def render_cape_dirty(_data, _part, _target_width, _options), do: :erlang.nif_error(:nif_not_loaded)
 end
@@ -4,6 +4,7 @@ defmodule GlobalApi.SocketManager do
   alias GlobalApi.DatabaseQueue
   alias GlobalApi.JavaSkinsRepo
   alias GlobalApi.SkinPreQueue
+  alias GlobalApi.SkinsNif
   alias GlobalApi.SkinsRepo
   alias GlobalApi.Utils
 
@@ -112,12 +113,15 @@ defmodule GlobalApi.SocketManager do
     xuids = :ets.take(:skin_pending, rgba_hash)
       |> handle_skin_uploaded(MapSet.new(), rgba_hash, data_map)
 
+    # skins are only uploaded after being converted by this node, so they have its hash version
+    hash_version = SkinsNif.hash_version()
+
     DatabaseQueue.async_fn_call(fn ->
-      skin_id = SkinsRepo.create_or_get_unique_skin(%{data_map | hash: rgba_hash})
+      skin_id = SkinsRepo.create_or_get_unique_skin(Map.merge(data_map, %{hash: rgba_hash, hash_version: hash_version}))
 
       Cachex.put(
         :hash_to_skin,
-        {rgba_hash, data_map.is_steve},
+        {rgba_hash, data_map.is_steve, hash_version},
         {skin_id, data_map.texture_id, data_map.value, data_map.signature}
       )
 
@@ -0,0 +1,46 @@
+defmodule GlobalApi.TokenKeyFetcher do
+  use GenServer
+
+  alias GlobalApi.SkinsNif
+
+  def start_link(init_arg) do
+    GenServer.start_link(__MODULE__, init_arg, name: __MODULE__)
+  end
+
+  @impl true
+  def init(_init_arg) do
+    # token logins are disabled without a discovery url, so there are no keys to fetch
+    if Application.get_env(:global_api, :token_discovery_url) != nil do
+      queue_fetch(0)
+    end
+    # the last JWKS document, the keys are only rotated when it changed
+    {:ok, nil}
+  end
+
+  @impl true
+  def handle_info(:fetch, last_jwks) do
+    discovery = HTTPoison.get!(Application.get_env(:global_api, :token_discovery_url))
+    jwks_uri = Jason.decode!(discovery.body)["jwks_uri"]
+    jwks = HTTPoison.get!(jwks_uri).body
+
+    # the previous keys stay active, so rotating the same keys again would drop the actual previous keys
+    last_jwks =
+      if jwks != last_jwks do
+        case SkinsNif.set_token_keys(jwks) do
+          :ok -> jwks
+          {:error, reason} ->
+            Sentry.capture_message("Failed to load the token keys", extra: %{reason: reason})
+            last_jwks
+        end
+      else
+        last_jwks
+      end
+
+    queue_fetch()
+    {:noreply, last_jwks}
+  end
+
+  defp queue_fetch(wait_millis \\ 60 * 60_000) do
+    Process.send_after(self(), :fetch, wait_millis)
+  end
+end
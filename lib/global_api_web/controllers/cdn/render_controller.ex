@@ -32,6 +32,11 @@ defmodule GlobalApiWeb.Cdn.RenderController do
                   |> put_status(:bad_gateway)
                   |> put_resp_header("cache-control", "max-age=30, public")
                   |> json(%{message: "expected valid image from Minecraft"})
+                error when error in [:invalid_skin_width, :invalid_skin_height, :skin_too_large] ->
+                  conn
+                  |> put_status(:bad_gateway)
+                  |> put_resp_header("cache-control", "max-age=30, public")
+                  |> json(%{message: "expected skin with valid dimensions from Minecraft"})
                 render ->
                   conn
                   |> put_resp_header("cache-control", "max-age=86400, immutable, public")
@@ -108,11 +108,41 @@ defmodule GlobalApiWeb.WebSocket do
 
   def websocket_handle({:json, %{"chain_data" => chain_data, "client_data" => client_data}}, state)
       when is_list(chain_data) and is_binary(client_data) do
+    handle_login(state, client_data, fn -> SkinsNif.validate_and_convert(chain_data, client_data) end)
+  end
+
+  # the token logins of newer Bedrock versions, which are disabled when there is no token issuer
+  def websocket_handle({:json, %{"token" => token, "client_data" => client_data}}, state)
+      when is_binary(token) and is_binary(client_data) do
+    issuer = Application.get_env(:global_api, :token_issuer)
+    if is_nil(issuer) do
+      {[{:close, @invalid_data}], state}
+    else
+      audience = Application.get_env(:global_api, :token_audience)
+      handle_login(state, client_data, fn ->
+        SkinsNif.validate_token_and_convert(token, client_data, issuer, audience)
+      end)
+    end
+  end
+
+  def websocket_handle({:json, _}, state) do
+    {[{:close, 1007, @invalid_data}], state}
+  end
+
+  def websocket_handle({:text, _}, state) do
+    {[{:close, 1007, @invalid_action}], state}
+  end
+
+  defp handle_login(state, client_data, validate_and_convert) do
     try do
-      case SkinsNif.validate_and_convert(chain_data, client_data) do
+      case validate_and_convert.() do
         :invalid_data ->
           {[{:close, @invalid_data}], state}
 
+        :replayed ->
+          send_log_message(state, @info, "received a login that has already been submitted")
+          {:ok, state}
+
         {:invalid_size, extra_data} ->
           handle_extra_data(extra_data)
 
@@ -131,14 +161,27 @@ defmodule GlobalApiWeb.WebSocket do
           send_log_message(state, @info, "received a skin with invalid geometry: #{reason}")
           {:ok, state}
 
-        {is_steve, png, rgba_hash, minecraft_hash, {xuid, _, _} = extra_data} ->
+        {_, _, _, _, nil, %{authenticated: false}} ->
+          # self-signed chains can claim any xuid, so nothing of them is stored
+          send_log_message(state, @debug, "accepted a self-signed (offline mode) login chain, the skin isn't stored")
+          {:ok, state}
+
+        {is_steve, png, rgba_hash, minecraft_hash, {xuid, _, _} = extra_data, diagnostics} ->
           handle_extra_data(extra_data)
 
+          if diagnostics.filled_base_pixels > 0 do
+            send_log_message(
+              state,
+              @debug,
+              "filled #{diagnostics.filled_base_pixels} transparent base layer pixels (#{diagnostics.base_layer_fill_policy})"
+            )
+          end
+
           # check for cached skin
           {:ok, entry} = Cachex.get(:xuid_to_skin, xuid)
           if entry != nil do
             # the player's skin is cached, let's go to part 2
-            part_two(state, xuid, is_steve, png, rgba_hash, minecraft_hash, entry)
+            part_two(state, xuid, is_steve, png, rgba_hash, minecraft_hash, diagnostics.hash_version, entry)
           else
             #todo should probably get the player skin first
             # and when the actual skin isn't cached get the unique_skin
@@ -152,12 +195,12 @@ defmodule GlobalApiWeb.WebSocket do
                 unique_skin.value,
                 unique_skin.signature
               }
-              Cachex.put(:hash_to_skin, {unique_skin.hash, unique_skin.is_steve}, entry)
+              Cachex.put(:hash_to_skin, {unique_skin.hash, unique_skin.is_steve, unique_skin.hash_version}, entry)
 
               # the player's skin isn't cached, let's go to part 2
-              part_two(state, xuid, is_steve, png, rgba_hash, minecraft_hash, UniqueSkin.to_protected(player_skin.skin, player_skin))
+              part_two(state, xuid, is_steve, png, rgba_hash, minecraft_hash, diagnostics.hash_version, UniqueSkin.to_protected(player_skin.skin, player_skin))
             else
-              part_two(state, xuid, is_steve, png, rgba_hash, minecraft_hash, %{})
+              part_two(state, xuid, is_steve, png, rgba_hash, minecraft_hash, diagnostics.hash_version, %{})
             end
           end
           {:ok, state}
@@ -177,19 +220,15 @@ defmodule GlobalApiWeb.WebSocket do
     end
   end
 
-  def websocket_handle({:json, _}, state) do
-    {[{:close, 1007, @invalid_data}], state}
-  end
-
-  def websocket_handle({:text, _}, state) do
-    {[{:close, 1007, @invalid_action}], state}
-  end
+  # self-signed (offline mode) chains don't have extra data
+  defp handle_extra_data(nil), do: :ok
 
   defp handle_extra_data(extra_data) do
     XboxRepo.handle_extra_data(extra_data)
   end
 
-  defp part_two(state, xuid, is_steve, png, rgba_hash, minecraft_hash, skin_data) do
+  # hashes are only compared with (cached) hashes of the same hash version
+  defp part_two(state, xuid, is_steve, png, rgba_hash, minecraft_hash, hash_version, skin_data) do
     hash = if map_size(skin_data) != 0, do: skin_data[:hash]
 
     # since the skin hasn't changed since we last cached it we have to do nothing
@@ -221,12 +260,12 @@ defmodule GlobalApiWeb.WebSocket do
       )
     else
       # if the cached skin of the xuid doesn't match, we'll have to check if the skin itself is cached
-      {:ok, entry} = Cachex.get(:hash_to_skin, {rgba_hash, is_steve})
+      {:ok, entry} = Cachex.get(:hash_to_skin, {rgba_hash, is_steve, hash_version})
 
       # skin is already uploaded, but the player doesn't have it
       if entry != nil do
         # apparently this hash is popular, so we'll reset the expire time
-        Cachex.put(:hash_to_skin, {rgba_hash, is_steve}, entry)
+        Cachex.put(:hash_to_skin, {rgba_hash, is_steve, hash_version}, entry)
 
         {skin_id, texture_id, skin_value, skin_signature} = entry
 
@@ -269,7 +308,7 @@ defmodule GlobalApiWeb.WebSocket do
         )
       else
         # skin isn't cached on the server. Let's ask the database
-        unique_skin = SkinsRepo.get_unique_skin(rgba_hash, is_steve)
+        unique_skin = SkinsRepo.get_unique_skin(rgba_hash, is_steve, hash_version)
 
         # skin is already uploaded, but the player doesn't have it
         if unique_skin != nil do
@@ -292,7 +331,7 @@ defmodule GlobalApiWeb.WebSocket do
             unique_skin.value,
             unique_skin.signature
           }
-          Cachex.put(:hash_to_skin, {rgba_hash, is_steve}, entry)
+          Cachex.put(:hash_to_skin, {rgba_hash, is_steve, hash_version}, entry)
 
           if hash == nil do
             new_player_notify()
@@ -0,0 +1,30 @@
+use rustler::NifUnitEnum;
+
+use crate::common::OffsetAndDimension;
+use crate::common::skin::SkinFace;
+use crate::common::texture::box_uv_position_face;
+
+/// the parts that are stored in a 64x32 cape texture
+#[derive(PartialEq, Eq, Hash, NifUnitEnum)]
+pub enum CapePart {
+    Cape,
+    /// a single wing, the other wing is the same texture but mirrored
+    Elytra,
+}
+
+pub fn cape_texture_position_face(part: &CapePart, face: &SkinFace) -> OffsetAndDimension {
+    // both parts are regular cubes, so they follow the box uv layout
+    match part {
+        CapePart::Cape => box_uv_position_face(0, 0, 10, 16, 1, face),
+        CapePart::Elytra => box_uv_position_face(22, 0, 10, 20, 2, face),
+    }
+}
+
+/// the face that is visible when looking at the back of a player that wears the part.
+/// the cape is rotated 180 degrees in Minecraft, so its front faces away from the player
+pub fn cape_visible_face(part: &CapePart) -> SkinFace {
+    match part {
+        CapePart::Cape => SkinFace::Front,
+        CapePart::Elytra => SkinFace::Back,
+    }
+}
@@ -0,0 +1,144 @@
+use image::{Delay, Frame, RgbaImage};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image_webp::{ColorType, WebPEncoder};
+use lodepng::FilterStrategy;
+use rustler::NifUnitEnum;
+
+/// the image formats renders can be returned in
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, NifUnitEnum)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    /// lossless WebP, a lot smaller than png for most renders
+    Webp,
+    Gif,
+    /// animated png, a normal png when there is only one frame
+    Apng,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// the image couldn't be encoded, which happens for images without any pixels
+    InvalidImage,
+    /// the format can't contain an animation
+    UnsupportedAnimation,
+}
+
+/// encodes a single image in the given format
+pub fn encode_image(image: &RgbaImage, format: OutputFormat) -> Result<Vec<u8>, EncodeError> {
+    if image.width() == 0 || image.height() == 0 {
+        return Err(EncodeError::InvalidImage);
+    }
+
+    match format {
+        OutputFormat::Png | OutputFormat::Apng => encode_optimized_png(image),
+        OutputFormat::Webp => encode_webp(image),
+        OutputFormat::Gif => encode_gif(std::slice::from_ref(image), 0),
+    }
+}
+
+/// encodes the frames as an animation that loops forever.
+/// WebP animations aren't supported, so animations can't be encoded as WebP
+/// and png is encoded as apng (which shows the first frame in viewers that don't support apng)
+pub fn encode_animation(frames: &[RgbaImage], frame_delay_ms: u16, format: OutputFormat) -> Result<Vec<u8>, EncodeError> {
+    if frames.len() == 1 {
+        return encode_image(&frames[0], format);
+    }
+    if frames.iter().any(|frame| frame.width() == 0 || frame.height() == 0) {
+        return Err(EncodeError::InvalidImage);
+    }
+
+    match format {
+        OutputFormat::Png | OutputFormat::Apng => encode_apng(frames, frame_delay_ms),
+        OutputFormat::Webp => Err(EncodeError::UnsupportedAnimation),
+        OutputFormat::Gif => encode_gif(frames, frame_delay_ms),
+    }
+}
+
+/// encodes the image like Minecraft does. The Minecraft hash is made over these bytes,
+/// so the settings should never be changed
+pub fn encode_minecraft_png(raw_data: &[u8], width: usize, height: usize) -> Vec<u8> {
+    let mut encoder = lodepng::Encoder::new();
+    encoder.set_auto_convert(false);
+    encoder.info_png_mut().interlace_method = 0; // should be 0 but just to be sure
+
+    let encoder_settings = encoder.settings_mut();
+    encoder_settings.zlibsettings.set_level(4);
+    encoder_settings.filter_strategy = FilterStrategy::ZERO;
+
+    encoder.encode(raw_data, width, height).unwrap()
+}
+
+fn encode_optimized_png(image: &RgbaImage) -> Result<Vec<u8>, EncodeError> {
+    let mut encoder = lodepng::Encoder::new();
+    // allows lodepng to pick a smaller color type (e.g. a palette) when the image allows it
+    encoder.set_auto_convert(true);
+
+    let encoder_settings = encoder.settings_mut();
+    encoder_settings.zlibsettings.set_level(9);
+    encoder_settings.filter_strategy = FilterStrategy::MINSUM;
+
+    encoder.encode(image.as_raw(), image.width() as usize, image.height() as usize)
+        .map_err(|_| EncodeError::InvalidImage)
+}
+
+fn encode_webp(image: &RgbaImage) -> Result<Vec<u8>, EncodeError> {
+    let mut encoded = Vec::new();
+    WebPEncoder::new(&mut encoded)
+        .encode(image.as_raw(), image.width(), image.height(), ColorType::Rgba8)
+        .map_err(|_| EncodeError::InvalidImage)?;
+    Ok(encoded)
+}
+
+fn encode_gif(frames: &[RgbaImage], frame_delay_ms: u16) -> Result<Vec<u8>, EncodeError> {
+    let mut encoded = Vec::new();
+    {
+        let mut encoder = GifEncoder::new(&mut encoded);
+        encoder.set_repeat(Repeat::Infinite).map_err(|_| EncodeError::InvalidImage)?;
+
+        let delay = Delay::from_numer_denom_ms(frame_delay_ms as u32, 1);
+        let frames = frames.iter().map(|frame| Frame::from_parts(frame.clone(), 0, 0, delay));
+        encoder.encode_frames(frames).map_err(|_| EncodeError::InvalidImage)?;
+    }
+    Ok(encoded)
+}
+
+fn encode_apng(frames: &[RgbaImage], frame_delay_ms: u16) -> Result<Vec<u8>, EncodeError> {
+    let (width, height) = frames[0].dimensions();
+
+    let mut encoded = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut encoded, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_compression(png::Compression::Best);
+        encoder.set_animated(frames.len() as u32, 0).map_err(|_| EncodeError::InvalidImage)?;
+        encoder.set_frame_delay(frame_delay_ms, 1000).map_err(|_| EncodeError::InvalidImage)?;
+        // frames can be (partially) transparent, so they shouldn't be drawn over the previous frame
+        encoder.set_dispose_op(png::DisposeOp::Background).map_err(|_| EncodeError::InvalidImage)?;
+        encoder.set_blend_op(png::BlendOp::Source).map_err(|_| EncodeError::InvalidImage)?;
+
+        let mut writer = encoder.write_header().map_err(|_| EncodeError::InvalidImage)?;
+        for frame in frames {
+            writer.write_image_data(frame.as_raw()).map_err(|_| EncodeError::InvalidImage)?;
+        }
+        writer.finish().map_err(|_| EncodeError::InvalidImage)?;
+    }
+    Ok(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_animated_webp() {
+        let frames = vec![RgbaImage::new(8, 8); 2];
+        assert_eq!(encode_animation(&frames, 100, OutputFormat::Webp), Err(EncodeError::UnsupportedAnimation));
+        assert!(encode_animation(&frames[..1], 100, OutputFormat::Webp).is_ok());
+
+        for format in [OutputFormat::Png, OutputFormat::Apng, OutputFormat::Gif] {
+            assert!(encode_animation(&frames, 100, format).is_ok());
+        }
+    }
+}
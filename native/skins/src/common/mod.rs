@@ -1,11 +1,18 @@
 use rustler::NifTuple;
 
+pub mod cape;
+pub mod encoder;
 pub mod geometry;
 pub mod skin;
 pub mod texture;
+pub mod texture_decoder;
 
 pub const RGBA_CHANNELS: usize = 4;
+/// the dimensions of a Java skin
+pub const SKIN_WIDTH: usize = 64;
+pub const SKIN_HEIGHT: usize = 64;
 
+#[allow(dead_code)]
 #[derive(NifTuple)]
 pub struct Dimension {
     pub width: usize,
@@ -17,7 +24,7 @@ pub struct Offset {
     pub y_offset: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, NifTuple)]
 pub struct OffsetAndDimension {
     pub x_offset: usize,
     pub y_offset: usize,
@@ -2,13 +2,13 @@ use lazy_static::lazy_static;
 use rgb::ComponentBytes;
 use rustler::NifUnitEnum;
 
-#[derive(PartialEq, Eq, NifUnitEnum)]
+#[derive(PartialEq, Eq, Hash, NifUnitEnum)]
 pub enum SkinModel {
     Classic,
     Slim,
 }
 
-#[derive(PartialEq, Eq)]
+#[derive(PartialEq, Eq, NifUnitEnum)]
 pub enum SkinPart {
     Head,
     ArmLeft,
@@ -18,7 +18,7 @@ pub enum SkinPart {
     LegRight,
 }
 
-#[derive(PartialEq, Eq, NifUnitEnum)]
+#[derive(PartialEq, Eq, Hash, NifUnitEnum)]
 pub enum SkinLayer {
     Bottom,
     Top,
@@ -35,8 +35,25 @@ pub enum SkinFace {
     Back,
 }
 
+pub const PARTS: [SkinPart; 6] = [
+    SkinPart::Head, SkinPart::Body, SkinPart::ArmRight, SkinPart::ArmLeft, SkinPart::LegRight, SkinPart::LegLeft
+];
+
+pub const FACES: [SkinFace; 6] = [
+    SkinFace::Top, SkinFace::Bottom, SkinFace::Right, SkinFace::Front, SkinFace::Left, SkinFace::Back
+];
+
 pub struct SkinSection<'a>(pub &'a SkinPart, pub SkinLayer);
 
+/// how many pixels the overlay (top) layer of a part is inflated on every side in Minecraft.
+/// the hat is inflated by 0.5, while the jacket, sleeves and pants are inflated by 0.25
+pub fn overlay_inflation(part: &SkinPart) -> f64 {
+    match part {
+        SkinPart::Head => 0.5,
+        _ => 0.25,
+    }
+}
+
 lazy_static! {
     // we only need the raw content since converted skins will always be 64x64
     pub static ref ALEX_SKIN: Vec<u8> = {
@@ -1,5 +1,12 @@
-use crate::common::{OffsetAndDimension, RGBA_CHANNELS};
-use crate::common::skin::{SkinFace, SkinLayer, SkinModel, SkinPart, SkinSection};
+use lazy_static::lazy_static;
+
+use crate::common::{OffsetAndDimension, RGBA_CHANNELS, SKIN_HEIGHT, SKIN_WIDTH};
+use crate::common::skin::{FACES, PARTS, SkinFace, SkinLayer, SkinModel, SkinPart, SkinSection};
+
+lazy_static! {
+    static ref CLASSIC_USED_PIXELS: Vec<bool> = create_used_pixel_mask(&SkinModel::Classic);
+    static ref SLIM_USED_PIXELS: Vec<bool> = create_used_pixel_mask(&SkinModel::Slim);
+}
 
 pub fn texture_position(section: SkinSection) -> Option<OffsetAndDimension> {
     // start x, start y, width, height
@@ -57,7 +64,7 @@ pub fn texture_position_face(part: &SkinPart, layer: &SkinLayer, face: &SkinFace
 
     } else if part == &SkinPart::Body {
         if face == &SkinFace::Left || face == &SkinFace::Right {
-            x_offset = if face == &SkinFace::Left { 16 } else { 28 };
+            x_offset = if face == &SkinFace::Right { 16 } else { 28 };
             y_offset = 20;
             width = 4;
             height = 12;
@@ -123,7 +130,7 @@ pub fn texture_position_face(part: &SkinPart, layer: &SkinLayer, face: &SkinFace
                     },
                     SkinFace::Left => x_offset += 4 + arm_width,
                     SkinFace::Back => {
-                        x_offset += 4 + arm_width;
+                        x_offset += 4 + arm_width + 4;
                         width = arm_width
                     },
                     _ => panic!()
@@ -152,6 +159,47 @@ pub fn texture_position_face(part: &SkinPart, layer: &SkinLayer, face: &SkinFace
     Some(OffsetAndDimension { x_offset, y_offset, width, height })
 }
 
+/// whether a pixel of a 64x64 skin is used by any face of the model, row by row
+pub fn used_pixel_mask(model: &SkinModel) -> &'static [bool] {
+    match model {
+        SkinModel::Classic => &CLASSIC_USED_PIXELS,
+        SkinModel::Slim => &SLIM_USED_PIXELS,
+    }
+}
+
+fn create_used_pixel_mask(model: &SkinModel) -> Vec<bool> {
+    let mut mask = vec![false; SKIN_WIDTH * SKIN_HEIGHT];
+
+    for part in PARTS {
+        for layer in [SkinLayer::Bottom, SkinLayer::Top] {
+            for face in FACES {
+                let Some(position) = texture_position_face(&part, &layer, &face, model) else { continue };
+
+                for y in position.y_offset..position.y_offset + position.height {
+                    let row = y * SKIN_WIDTH;
+                    mask[row + position.x_offset..row + position.x_offset + position.width].fill(true);
+                }
+            }
+        }
+    }
+    mask
+}
+
+/// the position of a face of a cube that uses Minecraft's box uv layout.
+/// u and v are the texture offset of the cube, the rest is the size of the cube
+pub fn box_uv_position_face(u: usize, v: usize, width: usize, height: usize, depth: usize, face: &SkinFace)
+    -> OffsetAndDimension {
+
+    let new = OffsetAndDimension::new;
+    match face {
+        SkinFace::Top => new(u + depth, v, width, depth),
+        SkinFace::Bottom => new(u + depth + width, v, width, depth),
+        SkinFace::Right => new(u, v + depth, depth, height),
+        SkinFace::Front => new(u + depth, v + depth, width, height),
+        SkinFace::Left => new(u + depth + width, v + depth, depth, height),
+        SkinFace::Back => new(u + depth * 2 + width, v + depth, width, height),
+    }
+}
 
 pub fn scale_and_fill_texture(
     source_data: &[u8],
@@ -262,6 +310,7 @@ pub fn scale_and_fill_texture(
     }
 }
 
+#[allow(dead_code)]
 pub fn set_rgb_pixel(image: &mut [u8], width: usize, x: usize, y: usize, r: u8, g: u8, b: u8) {
     let pixel = (y * width + x) * RGBA_CHANNELS;
     if image.len() < pixel + 3 {
@@ -0,0 +1,326 @@
+use std::ops::Range;
+
+use png::{ColorType, Transformations};
+use rgb::ComponentBytes;
+
+use crate::common::RGBA_CHANNELS;
+
+/// the largest (HD) skin width we accept, larger skins take too much memory to decode
+pub const MAX_SKIN_WIDTH: usize = 1024;
+/// the largest amount of pixels of other textures (like capes and animated faces), for the same reason
+pub const MAX_TEXTURE_PIXELS: usize = 2048 * 2048;
+
+/// the width and height of the skins returned by decode_skin
+const SKIN_SIZE: usize = 64;
+
+/// the png signature followed by the length and type of the IHDR chunk, which always comes first
+const PNG_IHDR_START: [u8; 16] = [
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52
+];
+
+/// an image that has been converted to RGBA with 8 bits per channel
+pub struct DecodedImage {
+    pub data: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// the data isn't a png that can be decoded
+    InvalidImage,
+    /// the width of the skin isn't a multiple of 64
+    InvalidWidth,
+    /// the height of the skin isn't the width (64x64) or half the width (legacy 64x32)
+    InvalidHeight,
+    /// the width of the skin is larger than MAX_SKIN_WIDTH,
+    /// or the texture has more than MAX_TEXTURE_PIXELS pixels
+    TooLarge,
+}
+
+/// decodes a png of any color type and bit depth to RGBA8.
+/// The dimensions are checked against MAX_TEXTURE_PIXELS before the image data is decoded
+pub fn decode_png(data: &[u8]) -> Result<DecodedImage, DecodeError> {
+    decode_png_with_limit(data, check_texture_dimensions)
+}
+
+/// decodes a png like decode_png, but also makes sure that it has the dimensions of a skin.
+/// The dimensions are checked before the image data is decoded.
+/// Legacy (64x32) skins are converted to 64x64 skins like Minecraft does,
+/// and HD skins are scaled down to 64x64 because the renders use the texture positions of a 64x64 skin
+pub fn decode_skin(data: &[u8]) -> Result<DecodedImage, DecodeError> {
+    let mut image = decode_png_with_limit(data, check_skin_dimensions)?;
+
+    if image.height * 2 == image.width {
+        image = convert_legacy_skin(&image);
+    }
+    if image.width > SKIN_SIZE {
+        image = scale_down_skin(&image);
+    }
+    Ok(image)
+}
+
+fn check_texture_dimensions(width: usize, height: usize) -> Result<(), DecodeError> {
+    if width.saturating_mul(height) > MAX_TEXTURE_PIXELS {
+        return Err(DecodeError::TooLarge);
+    }
+    Ok(())
+}
+
+fn check_skin_dimensions(width: usize, height: usize) -> Result<(), DecodeError> {
+    if width == 0 || !width.is_multiple_of(64) {
+        return Err(DecodeError::InvalidWidth);
+    }
+    if width > MAX_SKIN_WIDTH {
+        return Err(DecodeError::TooLarge);
+    }
+    if height != width && height * 2 != width {
+        return Err(DecodeError::InvalidHeight);
+    }
+    Ok(())
+}
+
+fn decode_png_with_limit(
+    data: &[u8],
+    check_dimensions: impl Fn(usize, usize) -> Result<(), DecodeError>
+) -> Result<DecodedImage, DecodeError> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(Transformations::normalize_to_color8());
+
+    let mut reader = match decoder.read_info() {
+        Ok(reader) => reader,
+        // lodepng is more lenient with some broken files
+        Err(_) => return decode_with_lodepng(data, check_dimensions),
+    };
+
+    let info = reader.info();
+    let (width, height) = (info.width as usize, info.height as usize);
+    check_dimensions(width, height)?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = match reader.next_frame(&mut buffer) {
+        Ok(frame) => frame,
+        Err(_) => return decode_with_lodepng(data, check_dimensions),
+    };
+    buffer.truncate(frame.buffer_size());
+
+    let pixel_count = width * height;
+    let data = match frame.color_type {
+        ColorType::Rgba => buffer,
+        ColorType::Rgb => buffer.chunks_exact(3).flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255]).collect(),
+        ColorType::GrayscaleAlpha => buffer.chunks_exact(2).flat_map(|grey| [grey[0], grey[0], grey[0], grey[1]]).collect(),
+        ColorType::Grayscale => buffer.iter().flat_map(|&grey| [grey, grey, grey, 255]).collect(),
+        // palettes are expanded to rgb(a) by the transformations
+        ColorType::Indexed => return decode_with_lodepng(data, check_dimensions),
+    };
+
+    if data.len() != pixel_count * RGBA_CHANNELS {
+        return Err(DecodeError::InvalidImage);
+    }
+    Ok(DecodedImage { data, width, height })
+}
+
+fn decode_with_lodepng(
+    data: &[u8],
+    check_dimensions: impl Fn(usize, usize) -> Result<(), DecodeError>
+) -> Result<DecodedImage, DecodeError> {
+    // lodepng only gives the dimensions after decoding, so they're read from the header first
+    let (width, height) = ihdr_dimensions(data).ok_or(DecodeError::InvalidImage)?;
+    check_dimensions(width, height)?;
+
+    let png = lodepng::decode32(data).map_err(|_| DecodeError::InvalidImage)?;
+    if png.width != width || png.height != height {
+        return Err(DecodeError::InvalidImage);
+    }
+
+    Ok(DecodedImage { data: png.buffer.as_bytes().to_vec(), width: png.width, height: png.height })
+}
+
+/// the width and height in the IHDR chunk of a png
+fn ihdr_dimensions(data: &[u8]) -> Option<(usize, usize)> {
+    if data.len() < PNG_IHDR_START.len() + 8 || data[..PNG_IHDR_START.len()] != PNG_IHDR_START {
+        return None;
+    }
+    let dimension = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize;
+    Some((dimension(PNG_IHDR_START.len()), dimension(PNG_IHDR_START.len() + 4)))
+}
+
+/// uses the top left pixel of every block of scale by scale pixels,
+/// so (semi) transparent pixels don't get mixed with the pixels around them
+fn scale_down_skin(image: &DecodedImage) -> DecodedImage {
+    let scale = image.width / SKIN_SIZE;
+
+    let mut data = Vec::with_capacity(SKIN_SIZE * SKIN_SIZE * RGBA_CHANNELS);
+    for y in 0..SKIN_SIZE {
+        for x in 0..SKIN_SIZE {
+            let source = (y * scale * image.width + x * scale) * RGBA_CHANNELS;
+            data.extend_from_slice(&image.data[source..source + RGBA_CHANNELS]);
+        }
+    }
+    DecodedImage { data, width: SKIN_SIZE, height: SKIN_SIZE }
+}
+
+/// legacy skins don't have a left arm and left leg, Minecraft uses the mirrored right arm and leg instead.
+/// Legacy skins also didn't support a transparent hat
+fn convert_legacy_skin(image: &DecodedImage) -> DecodedImage {
+    let width = image.width;
+    let scale = width / 64;
+
+    let mut data = vec![0; width * width * RGBA_CHANNELS];
+    data[..image.data.len()].copy_from_slice(&image.data);
+
+    // x, y, x offset, y offset, width and height, like Minecraft's HttpTexture
+    let copies: [(usize, usize, isize, isize, usize, usize); 12] = [
+        (4, 16, 16, 32, 4, 4),
+        (8, 16, 16, 32, 4, 4),
+        (0, 20, 24, 32, 4, 12),
+        (4, 20, 16, 32, 4, 12),
+        (8, 20, 8, 32, 4, 12),
+        (12, 20, 16, 32, 4, 12),
+        (44, 16, -8, 32, 4, 4),
+        (48, 16, -8, 32, 4, 4),
+        (40, 20, 0, 32, 4, 12),
+        (44, 20, -8, 32, 4, 12),
+        (48, 20, -16, 32, 4, 12),
+        (52, 20, -8, 32, 4, 12),
+    ];
+
+    for (x, y, x_offset, y_offset, copy_width, copy_height) in copies {
+        let (x, y, copy_width, copy_height) = (x * scale, y * scale, copy_width * scale, copy_height * scale);
+        let target_x = (x as isize + x_offset * scale as isize) as usize;
+        let target_y = (y as isize + y_offset * scale as isize) as usize;
+
+        for row in 0..copy_height {
+            for column in 0..copy_width {
+                // the copied part is mirrored horizontally
+                let source = ((y + row) * width + x + column) * RGBA_CHANNELS;
+                let target = ((target_y + row) * width + target_x + copy_width - 1 - column) * RGBA_CHANNELS;
+                data.copy_within(source..source + RGBA_CHANNELS, target);
+            }
+        }
+    }
+
+    remove_opaque_hat(&mut data, width, scale);
+
+    DecodedImage { data, width, height: width }
+}
+
+/// legacy skins often have a fully opaque hat, which would hide the head.
+/// Like Minecraft, the whole right half of the top is made transparent when none of its pixels are transparent.
+/// The right arm below the hat is part of the base layer, so Minecraft makes it opaque again afterwards
+fn remove_opaque_hat(data: &mut [u8], width: usize, scale: usize) {
+    let overlay_pixels = || area_pixels(width, scale, 32..64, 0..32);
+
+    if overlay_pixels().any(|pixel| data[pixel + 3] < 128) {
+        return;
+    }
+    for pixel in overlay_pixels() {
+        data[pixel + 3] = 0;
+    }
+    for pixel in area_pixels(width, scale, 32..64, 16..32) {
+        data[pixel + 3] = 255;
+    }
+}
+
+/// the index of every pixel in the area, which is given in the coordinates of a 64 pixels wide skin
+fn area_pixels(width: usize, scale: usize, x: Range<usize>, y: Range<usize>) -> impl Iterator<Item = usize> {
+    (y.start * scale..y.end * scale)
+        .flat_map(move |y| (x.start * scale..x.end * scale).map(move |x| (y * width + x) * RGBA_CHANNELS))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::common::encoder::encode_minecraft_png;
+    use crate::common::skin::STEVE_SKIN;
+
+    use super::*;
+
+    /// the offset of the crc of the IHDR chunk
+    const IHDR_CRC: usize = PNG_IHDR_START.len() + 13;
+
+    fn png(width: usize, height: usize) -> Vec<u8> {
+        encode_minecraft_png(&vec![255; width * height * RGBA_CHANNELS], width, height)
+    }
+
+    /// a png that only has the header of an image with the given dimensions, so only lodepng will try it
+    fn header_only(width: u32, height: u32) -> Vec<u8> {
+        [&PNG_IHDR_START[..], &width.to_be_bytes(), &height.to_be_bytes(), &[8, 6, 0, 0, 0]].concat()
+    }
+
+    fn pixel(data: &[u8], width: usize, x: usize, y: usize) -> &[u8] {
+        let index = (y * width + x) * RGBA_CHANNELS;
+        &data[index..index + RGBA_CHANNELS]
+    }
+
+    #[test]
+    fn rejects_invalid_skin_dimensions() {
+        assert_eq!(decode_skin(&png(48, 48)).err(), Some(DecodeError::InvalidWidth));
+        assert_eq!(decode_skin(&png(64, 48)).err(), Some(DecodeError::InvalidHeight));
+        assert_eq!(decode_skin(&png(2048, 2048)).err(), Some(DecodeError::TooLarge));
+        assert_eq!(decode_skin(b"not a png").err(), Some(DecodeError::InvalidImage));
+    }
+
+    #[test]
+    fn lodepng_checks_dimensions_before_decoding() {
+        // a broken crc makes the png crate fail, lodepng would fail as well but the header is checked first
+        let mut broken = png(48, 48);
+        broken[IHDR_CRC] ^= 0xff;
+        assert_eq!(decode_skin(&broken).err(), Some(DecodeError::InvalidWidth));
+
+        let mut broken = png(64, 64);
+        broken[IHDR_CRC] ^= 0xff;
+        assert_eq!(decode_skin(&broken).err(), Some(DecodeError::InvalidImage));
+
+        assert_eq!(decode_skin(&header_only(1 << 20, 1 << 20)).err(), Some(DecodeError::TooLarge));
+        assert_eq!(decode_png(&header_only(1 << 20, 1 << 20)).err(), Some(DecodeError::TooLarge));
+    }
+
+    #[test]
+    fn converts_legacy_skins() {
+        let legacy = &STEVE_SKIN[..64 * 32 * RGBA_CHANNELS];
+        let skin = decode_skin(&encode_minecraft_png(legacy, 64, 32)).unwrap();
+
+        assert_eq!((skin.width, skin.height), (64, 64));
+        assert_eq!(skin.data.len(), 64 * 64 * RGBA_CHANNELS);
+        // the top half is kept as is, Steve's hat is already transparent
+        assert_eq!(skin.data[..legacy.len()], *legacy);
+
+        // the front of the left leg is the mirrored front of the right leg
+        for y in 20..32 {
+            for x in 4..8 {
+                assert_eq!(pixel(&skin.data, 64, 20 + 7 - x, y + 32), pixel(legacy, 64, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn removes_opaque_hat_of_legacy_skins() {
+        let opaque = [255; 64 * 32 * RGBA_CHANNELS];
+        let skin = decode_skin(&encode_minecraft_png(&opaque, 64, 32)).unwrap();
+        // the hat is removed, but the right arm below it stays opaque
+        assert_eq!(pixel(&skin.data, 64, 40, 8)[3], 0);
+        assert_eq!(pixel(&skin.data, 64, 44, 20)[3], 255);
+
+        // a transparent pixel below the hat counts as well, so the hat is kept
+        let mut legacy = opaque;
+        legacy[(20 * 64 + 60) * RGBA_CHANNELS + 3] = 0;
+        let skin = decode_skin(&encode_minecraft_png(&legacy, 64, 32)).unwrap();
+        assert_eq!(pixel(&skin.data, 64, 40, 8)[3], 255);
+    }
+
+    #[test]
+    fn scales_hd_skins_down() {
+        let mut hd = vec![0; 128 * 128 * RGBA_CHANNELS];
+        for y in 0..128 {
+            for x in 0..128 {
+                let index = (y * 128 + x) * RGBA_CHANNELS;
+                hd[index..index + RGBA_CHANNELS].copy_from_slice(pixel(&STEVE_SKIN, 64, x / 2, y / 2));
+            }
+        }
+
+        let skin = decode_skin(&encode_minecraft_png(&hd, 128, 128)).unwrap();
+        assert_eq!((skin.width, skin.height), (64, 64));
+        assert_eq!(skin.data, *STEVE_SKIN);
+    }
+}
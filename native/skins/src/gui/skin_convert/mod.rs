@@ -93,7 +93,7 @@ impl SkinConvertData {
             source_image
         );
 
-        let short_name = char::from('a' as u8 + self.geometry_entries.len() as u8).to_string();
+        let short_name = char::from(b'a' + self.geometry_entries.len() as u8).to_string();
 
         let entry = SkinDebugGeometryEntry::new(
             geometry_name.to_string(), short_name, geometry_entry.pretty(4), source_image
@@ -1,14 +1,30 @@
-use rgb::ComponentBytes;
-use rustler::{atoms, Binary, Encoder, Env, init, ListIterator, nif, Term};
-use rustler::types::atom::{false_, true_};
+use image::RgbaImage;
+use rayon::prelude::*;
+use rustler::{Atom, atoms, Binary, Encoder, Env, init, nif, Term};
+use rustler::types::atom::{error, false_, nil, ok, true_};
 use rustler::types::tuple::make_tuple;
+use serde_json::Value;
 
+use crate::common::cape::CapePart;
+use crate::common::encoder::{encode_image, OutputFormat};
 use crate::common::skin::{SkinLayer, SkinModel};
-use crate::rustler_utils::as_binary;
+use crate::common::texture::used_pixel_mask;
+use crate::common::texture_decoder::{decode_skin, DecodeError};
+use crate::rustler_utils::{as_binary, encode_json, map_get_optional};
 use crate::skin_convert::{convert_skin, ConvertResult, ErrorType};
-use crate::skin_convert::chain_validator::validate_chain;
+use crate::skin_convert::chain_validator::{self, ChainError, ChainOptions, clock_skew, current_time, validate_chain, ValidatedChain};
+use crate::skin_convert::key_source::Jwks;
+use crate::skin_convert::login_identity::LoginIdentity;
+use crate::skin_convert::replay_guard;
 use crate::skin_convert::skin_codec::ImageWithHashes;
-use crate::skin_render::flat_render::render_front;
+use crate::skin_convert::skin_validator;
+use crate::skin_convert::token_validator::{self, CLIENT_KEY_CLAIM, GAMERTAG_CLAIM, TOKEN_KEYS, TokenOptions, XUID_CLAIM};
+use crate::skin_render::{profile_card, render_cache, sprite_sheet};
+use crate::skin_render::pose::Pose;
+use crate::skin_render::sprite_sheet::{MAX_SPRITE_SKINS, SpriteSkin};
+use crate::skin_render::render_cache::RenderCacheStats;
+use crate::skin_render::render_request::{RenderError, RenderRequest};
+use crate::skin_render::{MAX_TARGET_WIDTH, RenderOptions};
 
 mod common;
 mod skin_render;
@@ -22,27 +38,92 @@ atoms! {
     invalid_size,
     invalid_image,
     invalid_geometry,
+    unsupported_format,
     hash_doesnt_match,
+    authenticated,
+    hash_version,
+    perceptual_hash,
+    invalid_hash,
+
+    // decode
+    invalid_skin_width,
+    invalid_skin_height,
+    skin_too_large,
+
+    // load
+    render_cache_size,
+    base_layer_fill,
+    chain_clock_skew,
+    offline_chains,
+    chain_max_age,
+    replay_capacity,
+    skin_hash_version,
 }
 
 #[nif(schedule = "DirtyCpu")]
-pub fn validate_and_convert<'a>(env: Env<'a>, chain_data: Term<'a>, client_data: &'a str) -> Term<'a> {
-    let list_iterator: ListIterator = chain_data.decode().unwrap();
-    let validation_result = validate_chain(list_iterator, client_data);
-
-    if validation_result.is_none() {
+pub fn validate_and_convert<'a>(env: Env<'a>, chain_data: Vec<&'a str>, client_data: &'a str) -> Term<'a> {
+    let Ok(ValidatedChain { identity_claims, client_claims, authenticated: is_authenticated, valid_until }) =
+        validate_chain(&chain_data, client_data, &ChainOptions::current()) else {
         return invalid_data().to_term(env);
+    };
+    if let Some(error) = check_replay(env, client_data, valid_until) {
+        return error;
     }
 
-    let (last_data, client_claims) = validation_result.unwrap();
-
-    let extra_data = last_data.get("extraData").unwrap();
+    // the validator made sure that the extra data is present
+    let extra_data = &identity_claims["extraData"];
     let xuid = extra_data["XUID"].as_str().unwrap();
     let gamertag = extra_data["displayName"].as_str().unwrap();
-    let issued_at = last_data["iat"].as_i64().unwrap() * 1000; // seconds to ms
-    let extra_data = make_tuple(env, &[xuid.encode(env), gamertag.encode(env), issued_at.encode(env)]);
+    let issued_at = identity_claims["iat"].as_i64().unwrap_or_default();
+    convert_login(env, &client_claims, xuid, gamertag, issued_at, is_authenticated)
+}
+
+/// validate_and_convert for the token logins of the newer Bedrock versions.
+/// The token is validated with the keys set by set_token_keys and has to contain the key that signed the client data
+#[nif(schedule = "DirtyCpu")]
+pub fn validate_token_and_convert<'a>(env: Env<'a>, token: &'a str, client_data: &'a str, issuer: String, audience: Option<String>) -> Term<'a> {
+    let options = TokenOptions { issuer, audience, clock_skew: clock_skew(), now: current_time() };
+    let Ok(claims) = token_validator::validate_token(token, &*TOKEN_KEYS.read().unwrap(), &options) else {
+        return invalid_data().to_term(env);
+    };
+    let (Some(xuid), Some(gamertag), Some(client_key)) =
+        (claims[XUID_CLAIM].as_str(), claims[GAMERTAG_CLAIM].as_str(), claims[CLIENT_KEY_CLAIM].as_str()) else {
+        return invalid_data().to_term(env);
+    };
+    let Ok(client_claims) = chain_validator::validate_client_data(client_data, client_key) else {
+        return invalid_data().to_term(env);
+    };
 
-    match convert_skin(&client_claims) {
+    // the validator made sure that exp is present
+    let valid_until = claims["exp"].as_u64().unwrap_or_default().saturating_add(clock_skew());
+    if let Some(error) = check_replay(env, client_data, valid_until) {
+        return error;
+    }
+
+    let issued_at = claims["iat"].as_i64().unwrap_or_default();
+    convert_login(env, &client_claims, xuid, gamertag, issued_at, true)
+}
+
+/// only the submissions are guarded, validate_chain can be called for the same login without being a replay
+fn check_replay<'a>(env: Env<'a>, client_data: &str, valid_until: u64) -> Option<Term<'a>> {
+    match replay_guard::check_replay(client_data, valid_until, current_time()) {
+        Ok(()) => None,
+        Err(error @ ChainError::Replayed) => Some(error.encode(env)),
+        Err(_) => Some(invalid_data().to_term(env)),
+    }
+}
+
+/// converts the skin of a validated login, the issued at is in seconds
+fn convert_login<'a>(env: Env<'a>, client_claims: &Value, xuid: &str, gamertag: &str, issued_at: i64, is_authenticated: bool) -> Term<'a> {
+    let issued_at = issued_at * 1000; // seconds to ms
+    // self-signed chains can claim any identity, so their extra data is nil to keep it from being stored
+    let extra_data = if is_authenticated {
+        make_tuple(env, &[xuid.encode(env), gamertag.encode(env), issued_at.encode(env)])
+    } else {
+        nil().to_term(env)
+    };
+
+    match convert_skin(client_claims) {
         ConvertResult::Invalid(err) => {
             let atom = match err {
                 ErrorType::InvalidSize => invalid_size(),
@@ -53,34 +134,379 @@ pub fn validate_and_convert<'a>(env: Env<'a>, chain_data: Term<'a>, client_data:
         ConvertResult::Error(err) =>
             make_tuple(env, &[invalid_geometry().to_term(env), err.encode(env), extra_data]),
 
-        ConvertResult::Success(ImageWithHashes { png, minecraft_hash, hash }, is_steve) => {
+        ConvertResult::Success(ImageWithHashes { png, minecraft_hash, hash, hash_version: version, perceptual_hash: dhash }, is_steve, diagnostics) => {
             let is_steve_atom = if is_steve { true_() } else { false_() };
-            make_tuple(env, &[is_steve_atom.to_term(env), as_binary(env, png.as_ref()), as_binary(env, hash.as_ref()), as_binary(env, minecraft_hash.as_ref()), extra_data])
+            // self-signed chains are only accepted in offline mode, the caller decides what to do with them
+            let diagnostics = diagnostics.encode(env)
+                .map_put(authenticated().encode(env), is_authenticated.encode(env)).unwrap()
+                .map_put(hash_version().encode(env), version.encode(env)).unwrap()
+                // converted skins are always 64x64
+                .map_put(perceptual_hash().encode(env), as_binary(env, &dhash.unwrap_or_default())).unwrap();
+            make_tuple(env, &[is_steve_atom.to_term(env), as_binary(env, png.as_ref()), as_binary(env, hash.as_ref()), as_binary(env, minecraft_hash.as_ref()), extra_data, diagnostics])
+        }
+    }
+}
+
+/// validates the login chain without converting the skin, returns the identity and device claims (see LoginIdentity)
+#[nif(name = "validate_chain", schedule = "DirtyCpu")]
+pub fn validate_login_chain<'a>(env: Env<'a>, chain_data: Vec<&'a str>, client_data: &'a str) -> Term<'a> {
+    match validate_chain(&chain_data, client_data, &ChainOptions::current()) {
+        Ok(chain) => make_tuple(env, &[ok().encode(env), LoginIdentity::from(&chain).encode(env)]),
+        Err(err) => make_tuple(env, &[error().encode(env), err.encode(env)]),
+    }
+}
+
+/// validates many (chain, client data) pairs at once, like validate_chain does for a single pair.
+/// Time checks can be disabled to verify historical chains, replays aren't checked
+#[nif(schedule = "DirtyCpu")]
+pub fn validate_chain_batch<'a>(env: Env<'a>, pairs: Vec<(Vec<&'a str>, &'a str)>, check_time: bool) -> Term<'a> {
+    let options = ChainOptions { check_time, ..ChainOptions::current() };
+
+    chain_validator::validate_chains(&pairs, options).into_iter()
+        .map(|result| match result {
+            Ok(chain) => make_tuple(env, &[ok().encode(env), LoginIdentity::from(&chain).encode(env)]),
+            Err(err) => make_tuple(env, &[error().encode(env), err.encode(env)]),
+        })
+        .collect::<Vec<Term>>()
+        .encode(env)
+}
+
+/// returns the violations of Mojang's skin rules, the violations that have been fixed and the fixed skin (or nil),
+/// see skin_validator
+#[nif(schedule = "DirtyCpu")]
+pub fn validate_java_skin<'a>(env: Env<'a>, data: Binary<'a>, model: SkinModel, auto_fix: bool) -> Term<'a> {
+    let result = skin_validator::validate_java_skin(data.as_slice(), &model, auto_fix);
+    let fixed = match result.fixed {
+        Some(fixed) => as_binary(env, &fixed),
+        None => nil().to_term(env),
+    };
+    make_tuple(env, &[result.violations.encode(env), result.fixed_violations.encode(env), fixed])
+}
+
+/// parses the JWKS document and makes its keys the newest keys that tokens are validated with
+#[nif]
+pub fn set_token_keys<'a>(env: Env<'a>, jwks: &'a str) -> Term<'a> {
+    match Jwks::parse(jwks) {
+        Ok(jwks) => {
+            TOKEN_KEYS.write().unwrap().rotate(jwks);
+            ok().encode(env)
         }
+        Err(err) => make_tuple(env, &[error().encode(env), err.encode(env)]),
+    }
+}
+
+/// validates the token with the keys set by set_token_keys and returns its claims
+#[nif]
+pub fn validate_token<'a>(env: Env<'a>, token: &'a str, issuer: String, audience: Option<String>) -> Term<'a> {
+    let options = TokenOptions { issuer, audience, clock_skew: clock_skew(), now: current_time() };
+
+    match token_validator::validate_token(token, &*TOKEN_KEYS.read().unwrap(), &options) {
+        Ok(claims) => make_tuple(env, &[ok().encode(env), encode_json(env, &claims)]),
+        Err(err) => make_tuple(env, &[error().encode(env), err.encode(env)]),
+    }
+}
+
+/// replaces the root keys that login chains have to be signed by, see chain_validator::set_trusted_keys.
+/// nil trusts the Mojang key again
+#[nif]
+pub fn set_trusted_keys(env: Env, keys: Option<Vec<String>>) -> Term {
+    let keys = keys.unwrap_or_else(|| vec![String::from(chain_validator::MOJANG_PUBLIC_KEY)]);
+    match chain_validator::set_trusted_keys(keys) {
+        Ok(()) => ok().encode(env),
+        Err(err) => make_tuple(env, &[error().encode(env), err.encode(env)]),
+    }
+}
+
+/// how alike the skins of two perceptual hashes (from validate_and_convert) are, from 0.0 to 1.0
+#[nif]
+pub fn skin_similarity<'a>(env: Env<'a>, first: Binary<'a>, second: Binary<'a>) -> Term<'a> {
+    match skin_convert::perceptual_hash::similarity(first.as_slice(), second.as_slice()) {
+        Some(similarity) => similarity.encode(env),
+        None => invalid_hash().encode(env),
     }
 }
 
+/// which pixels of a 64x64 skin are used by the model, one byte per pixel (row by row) that is 1 when it's used
 #[nif]
+pub fn skin_used_area_mask(env: Env, model: SkinModel) -> Term {
+    let mask: Vec<u8> = used_pixel_mask(&model).iter().map(|&used| used as u8).collect();
+    as_binary(env, &mask)
+}
+
+// renders that are quick for small sizes have a DirtyCpu variant for larger sizes,
+// the Elixir side picks the variant based on the target width
+
+#[nif(name = "render_skin_front_nif")]
 pub fn render_skin_front<'a>(
     env: Env<'a>,
     data: Binary<'a>,
     layer: SkinLayer,
     model: SkinModel,
     target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let request = RenderRequest::Front { data: data.as_slice(), layer, model, target_width, options };
+    encode_render_result(env, request.render())
+}
+
+#[nif(schedule = "DirtyCpu")]
+pub fn render_skin_front_dirty<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    layer: SkinLayer,
+    model: SkinModel,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let request = RenderRequest::Front { data: data.as_slice(), layer, model, target_width, options };
+    encode_render_result(env, request.render())
+}
+
+#[nif(name = "render_skin_back_nif")]
+pub fn render_skin_back<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    cape_data: Option<Binary<'a>>,
+    layer: SkinLayer,
+    model: SkinModel,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let cape_data = cape_data.map(|cape_data| cape_data.as_slice());
+    let request = RenderRequest::Back { data: data.as_slice(), cape_data, layer, model, target_width, options };
+    encode_render_result(env, request.render())
+}
+
+#[nif(schedule = "DirtyCpu")]
+pub fn render_skin_back_dirty<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    cape_data: Option<Binary<'a>>,
+    layer: SkinLayer,
+    model: SkinModel,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let cape_data = cape_data.map(|cape_data| cape_data.as_slice());
+    let request = RenderRequest::Back { data: data.as_slice(), cape_data, layer, model, target_width, options };
+    encode_render_result(env, request.render())
+}
+
+#[nif(name = "render_skin_isometric_nif")]
+pub fn render_skin_isometric<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    layer: SkinLayer,
+    model: SkinModel,
+    pose: Pose,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let request = RenderRequest::Isometric { data: data.as_slice(), layer, model, pose, target_width, options };
+    encode_render_result(env, request.render())
+}
+
+#[nif(schedule = "DirtyCpu")]
+pub fn render_skin_isometric_dirty<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    layer: SkinLayer,
+    model: SkinModel,
+    pose: Pose,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let request = RenderRequest::Isometric { data: data.as_slice(), layer, model, pose, target_width, options };
+    encode_render_result(env, request.render())
+}
+
+#[nif(schedule = "DirtyCpu")]
+#[allow(clippy::too_many_arguments)]
+pub fn render_bedrock_skin<'a>(
+    env: Env<'a>,
+    skin_data: Binary<'a>,
+    width: usize,
+    height: usize,
+    geometry_data: Binary<'a>,
+    resource_patch: Binary<'a>,
+    layer: SkinLayer,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let request = RenderRequest::Bedrock {
+        skin_data: skin_data.as_slice(),
+        width,
+        height,
+        geometry_data: geometry_data.as_slice(),
+        resource_patch: resource_patch.as_slice(),
+        layer,
+        target_width,
+        options,
+    };
+    encode_render_result(env, request.render())
+}
+
+#[nif(name = "render_cape_nif")]
+pub fn render_cape<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    part: CapePart,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let request = RenderRequest::Cape { data: data.as_slice(), part, target_width, options };
+    encode_render_result(env, request.render())
+}
+
+#[nif(schedule = "DirtyCpu")]
+pub fn render_cape_dirty<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    part: CapePart,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let request = RenderRequest::Cape { data: data.as_slice(), part, target_width, options };
+    encode_render_result(env, request.render())
+}
+
+#[nif(schedule = "DirtyCpu")]
+pub fn render_profile_card<'a>(
+    env: Env<'a>,
+    skin_data: Binary<'a>,
+    model: SkinModel,
+    gamertag: &'a str,
+    java_name: Option<&'a str>,
+    xuid: &'a str,
+    format: OutputFormat,
+) -> Term<'a> {
+    let skin = match decode_skin(skin_data.as_slice()) {
+        Ok(skin) => skin,
+        Err(err) => return decode_error_atom(err).to_term(env),
+    };
+
+    let card = profile_card::render_profile_card(&skin.data, skin.width, &model, gamertag, java_name, xuid);
+    encode_render(env, &card, format)
+}
+
+#[nif(schedule = "DirtyCpu")]
+pub fn render_sprite_sheet<'a>(
+    env: Env<'a>,
+    skins: Vec<(Binary<'a>, SkinModel)>,
+    columns: usize,
+    layer: SkinLayer,
+    target_width: usize,
+    options: RenderOptions,
 ) -> Term<'a> {
-    let png = lodepng::decode32(data.as_slice());
-    if png.is_err() {
-        return invalid_image().to_term(env);
+    if target_width > MAX_TARGET_WIDTH || skins.len() > MAX_SPRITE_SKINS {
+        return invalid_size().to_term(env);
+    }
+
+    let decoded: Vec<_> = skins.iter()
+        .map(|(data, model)| (decode_skin(data.as_slice()).ok(), model))
+        .collect();
+
+    let sprite_skins: Vec<SpriteSkin> = decoded.iter()
+        .map(|(skin, model)| skin.as_ref().map(|skin| (skin.data.as_slice(), skin.width, *model)))
+        .collect();
+
+    let Some((sheet, manifest)) =
+        sprite_sheet::render_sprite_sheet(&sprite_skins, columns, &layer, target_width, &options) else {
+        return invalid_size().to_term(env);
+    };
+    match encode_image(&sheet, options.format) {
+        Ok(encoded) => make_tuple(env, &[as_binary(env, &encoded), manifest.encode(env)]),
+        Err(_) => invalid_size().to_term(env),
     }
-    let png = png.unwrap();
+}
+
+#[nif(schedule = "DirtyCpu")]
+pub fn render_animated_face<'a>(
+    env: Env<'a>,
+    data: Binary<'a>,
+    frame_count: usize,
+    frame_delay_ms: u16,
+    layer: SkinLayer,
+    target_width: usize,
+    options: RenderOptions,
+) -> Term<'a> {
+    let request = RenderRequest::AnimatedFace {
+        data: data.as_slice(), frame_count, frame_delay_ms, layer, target_width, options
+    };
+    encode_render_result(env, request.render())
+}
+
+/// renders every request in parallel, the results are in the same order as the requests
+#[nif(schedule = "DirtyCpu")]
+pub fn render_batch<'a>(env: Env<'a>, requests: Vec<RenderRequest<'a>>) -> Term<'a> {
+    let results: Vec<_> = requests.par_iter()
+        .map(|request| request.render())
+        .collect();
+
+    let results: Vec<Term> = results.into_iter()
+        .map(|result| encode_render_result(env, result))
+        .collect();
+    results.encode(env)
+}
+
+#[nif]
+pub fn render_cache_stats() -> RenderCacheStats {
+    render_cache::stats()
+}
 
-    let render = render_front(png.buffer.as_bytes(), png.width, &layer, &model, target_width);
+fn encode_render_result(env: Env, result: Result<Vec<u8>, RenderError>) -> Term {
+    match result {
+        Ok(encoded) => as_binary(env, &encoded),
+        Err(RenderError::InvalidTexture(err)) => decode_error_atom(err).to_term(env),
+        Err(RenderError::InvalidSize) => invalid_size().to_term(env),
+        Err(RenderError::InvalidGeometry(err)) => make_tuple(env, &[invalid_geometry().to_term(env), err.encode(env)]),
+        Err(RenderError::UnsupportedFormat) => unsupported_format().to_term(env),
+    }
+}
 
-    let encoded = lodepng::encode32(render.as_ref(), render.width() as usize, render.height() as usize)
-        .expect("failed to encode image");
+fn decode_error_atom(err: DecodeError) -> Atom {
+    match err {
+        DecodeError::InvalidImage => invalid_image(),
+        DecodeError::InvalidWidth => invalid_skin_width(),
+        DecodeError::InvalidHeight => invalid_skin_height(),
+        DecodeError::TooLarge => skin_too_large(),
+    }
+}
 
+fn encode_render<'a>(env: Env<'a>, render: &RgbaImage, format: OutputFormat) -> Term<'a> {
+    match encode_image(render, format) {
+        Ok(encoded) => as_binary(env, &encoded),
+        Err(_) => invalid_size().to_term(env),
+    }
+}
 
-    as_binary(env, encoded.as_ref())
+fn load(_env: Env, load_info: Term) -> bool {
+    // the load data is only a map when it has been configured
+    if let Ok(Some(max_size)) = map_get_optional(load_info, render_cache_size()) {
+        render_cache::set_max_size(max_size);
+    }
+    if let Ok(Some(fill)) = map_get_optional(load_info, base_layer_fill()) {
+        skin_convert::base_layer::set_base_layer_fill(fill);
+    }
+    if let Ok(Some(clock_skew)) = map_get_optional(load_info, chain_clock_skew()) {
+        chain_validator::set_clock_skew(clock_skew);
+    }
+    if let Ok(Some(max_age)) = map_get_optional(load_info, chain_max_age()) {
+        chain_validator::set_max_age(max_age);
+    }
+    if let Ok(Some(capacity)) = map_get_optional(load_info, replay_capacity()) {
+        replay_guard::configure(capacity);
+    }
+    if let Ok(Some(version)) = map_get_optional(load_info, skin_hash_version()) {
+        // a version that isn't supported would make every new hash incomparable
+        if !skin_convert::skin_codec::set_hash_version(version) {
+            return false;
+        }
+    }
+    if let Ok(Some(offline_mode)) = map_get_optional(load_info, offline_chains()) {
+        chain_validator::set_offline_mode(offline_mode);
+    }
+    true
 }
 
-init!("Elixir.GlobalApi.SkinsNif", [validate_and_convert, render_skin_front]);
+init!("Elixir.GlobalApi.SkinsNif", [validate_and_convert, validate_token_and_convert, validate_login_chain, validate_chain_batch, validate_java_skin, skin_used_area_mask, skin_similarity, set_trusted_keys, set_token_keys, validate_token, render_skin_front, render_skin_front_dirty, render_skin_back, render_skin_back_dirty, render_skin_isometric, render_skin_isometric_dirty, render_bedrock_skin, render_cape, render_cape_dirty, render_profile_card, render_sprite_sheet, render_animated_face, render_batch, render_cache_stats], load = load);
@@ -14,6 +14,7 @@ use crate::gui::start_gui;
 use crate::skin_codec::{encode_custom_image, ImageWithHashes};
 use crate::skin_convert::{ConvertResult, convert_skin, skin_codec};
 use crate::skin_render::flat_render::{render_front, render_section};
+use crate::skin_render::RenderOptions;
 
 mod common;
 mod skin_convert;
@@ -115,14 +116,15 @@ fn convert_single(mut client_data: String) -> Result<(), String> {
         ConvertResult::Error(err) =>
             Err(format!("An error happened while converting skins! {}", err)),
 
-        ConvertResult::Success(ImageWithHashes { png, minecraft_hash, hash }, is_steve) => {
+        ConvertResult::Success(ImageWithHashes { png, minecraft_hash, hash, hash_version, .. }, is_steve, diagnostics) => {
             println!("Took {:.2?} to convert skin", start_time.elapsed());
 
             let mc_hash_hex = write_hex(minecraft_hash.as_ref());
             let hash_hex = write_hex(hash.as_ref());
 
             println!("Successfully encoded the converted image! is steve? {:}", is_steve);
-            println!("Internal hash: {:}, Minecraft hash: {:}", hash_hex, mc_hash_hex);
+            println!("Filled {:} transparent base layer pixels", diagnostics.filled_base_pixels);
+            println!("Internal hash: {:}, Minecraft hash: {:} (version {:})", hash_hex, mc_hash_hex, hash_version);
 
             let mut file = File::create(format!("{:}.png", mc_hash_hex)).unwrap();
 
@@ -150,7 +152,7 @@ fn decode_and_save_image(raw_data: String) -> Result<(), String> {
 }
 
 fn encode_and_save_image(data: &[u8], w: usize, h: usize) {
-    let ImageWithHashes { png, minecraft_hash, hash: _hash } = encode_custom_image(data, w, h);
+    let ImageWithHashes { png, minecraft_hash, .. } = encode_custom_image(data, w, h);
 
     let mc_hash_hex = write_hex(minecraft_hash.as_ref());
 
@@ -183,7 +185,7 @@ fn render_skin(path: &str, data_width: usize, model: SkinModel, render_type: Ski
 
     let render: RgbaImage = match render_type {
         SkinRenderType::Front => {
-            render_front(data, data_width, &SkinLayer::Both, &model, 16)
+            render_front(data, data_width, &SkinLayer::Both, &model, 16, &RenderOptions::default())
         }
         SkinRenderType::Section(section) => {
             let mut target = RgbaImage::new(32, 16);
@@ -1,7 +1,46 @@
-use rustler::{Binary, Env, OwnedBinary, Term};
+use rustler::{Atom, Binary, Decoder, Encoder, Env, NifResult, OwnedBinary, Term};
+use rustler::types::atom::nil;
+use rustler::types::map::map_new;
+use serde_json::Value;
 
 pub fn as_binary<'a>(env: Env<'a>, data: &[u8]) -> Term<'a> {
     let mut erl_bin: OwnedBinary = OwnedBinary::new(data.len()).unwrap();
     erl_bin.as_mut_slice().copy_from_slice(data);
     Binary::from_owned(erl_bin, env).to_term(env)
-}
\ No newline at end of file
+}
+
+/// decodes the value of the given key, or returns None when the map doesn't have the key.
+/// fails when the given term isn't a map or when the value can't be decoded
+pub fn map_get_optional<'a, T: Decoder<'a>>(map: Term<'a>, key: Atom) -> NifResult<Option<T>> {
+    // map_size only succeeds on maps
+    map.map_size()?;
+
+    match map.map_get(key) {
+        Ok(value) => Ok(Some(value.decode()?)),
+        Err(_) => Ok(None)
+    }
+}
+
+/// encodes json as the terms that Jason would decode it to, objects become maps with string keys
+pub fn encode_json<'a>(env: Env<'a>, value: &Value) -> Term<'a> {
+    match value {
+        Value::Null => nil().encode(env),
+        Value::Bool(value) => value.encode(env),
+        Value::Number(number) => {
+            if let Some(number) = number.as_i64() {
+                number.encode(env)
+            } else if let Some(number) = number.as_u64() {
+                number.encode(env)
+            } else {
+                number.as_f64().unwrap_or_default().encode(env)
+            }
+        }
+        Value::String(value) => value.encode(env),
+        Value::Array(values) => values.iter()
+            .map(|value| encode_json(env, value))
+            .collect::<Vec<Term>>()
+            .encode(env),
+        Value::Object(map) => map.iter()
+            .fold(map_new(env), |term, (key, value)| term.map_put(key.encode(env), encode_json(env, value)).unwrap()),
+    }
+}
@@ -0,0 +1,182 @@
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use rustler::NifUnitEnum;
+
+use crate::common::{OffsetAndDimension, RGBA_CHANNELS};
+use crate::common::skin::{ALEX_SKIN, FACES, PARTS, SkinLayer, SkinModel, SkinPart, STEVE_SKIN};
+use crate::common::texture::texture_position_face;
+use crate::skin_convert::skin_codec::SKIN_WIDTH;
+
+/// what transparent base layer pixels are filled with,
+/// Java renders the base layer as opaque so transparent pixels would otherwise show up as black (or worse)
+#[derive(Clone, Copy, Default, PartialEq, Eq, NifUnitEnum)]
+pub enum BaseLayerFill {
+    /// the pixel of the default (Steve or Alex) skin
+    #[default]
+    DefaultSkin,
+    /// the closest opaque pixel of the same face, or the default skin when the face has no opaque pixels
+    NearestNeighbour,
+    Black,
+}
+
+lazy_static! {
+    /// None doesn't fill the base layer, filling changes the hashes of skins with a transparent base layer
+    static ref BASE_LAYER_FILL: RwLock<Option<BaseLayerFill>> = RwLock::new(None);
+}
+
+/// changes the fill policy used by every conversion, None disables filling
+pub fn set_base_layer_fill(fill: Option<BaseLayerFill>) {
+    *BASE_LAYER_FILL.write().unwrap() = fill;
+}
+
+pub fn base_layer_fill() -> Option<BaseLayerFill> {
+    *BASE_LAYER_FILL.read().unwrap()
+}
+
+/// makes every base layer pixel of a 64x64 skin opaque.
+/// Transparent pixels are replaced by the fill and semi-transparent pixels are blended on top of it.
+/// Returns the amount of pixels that have been changed
+pub fn fill_base_layer(raw_data: &mut [u8], is_steve: bool, fill: BaseLayerFill) -> usize {
+    let default_skin: &[u8] = if is_steve { &STEVE_SKIN } else { &ALEX_SKIN };
+    let model = if is_steve { SkinModel::Classic } else { SkinModel::Slim };
+
+    let mut filled = 0;
+    for part in PARTS {
+        for face in base_layer_faces(&part, 64, &model) {
+            // the opaque pixels of the face before anything was filled, used by the nearest neighbour fill
+            let opaque: Vec<(usize, usize)> = face_coordinates(&face)
+                .filter(|&(x, y)| raw_data[pixel_index(x, y) + 3] == 255)
+                .collect();
+
+            for (x, y) in face_coordinates(&face) {
+                let pixel = pixel_index(x, y);
+                let alpha = raw_data[pixel + 3];
+                if alpha == 255 {
+                    continue;
+                }
+
+                let fill_color = match fill {
+                    BaseLayerFill::Black => [0, 0, 0],
+                    BaseLayerFill::NearestNeighbour if !opaque.is_empty() => {
+                        let nearest = nearest(&opaque, x, y);
+                        rgb(raw_data, pixel_index(nearest.0, nearest.1))
+                    }
+                    _ => rgb(default_skin, pixel),
+                };
+
+                for channel in 0..3 {
+                    let color = raw_data[pixel + channel] as u32 * alpha as u32;
+                    let background = fill_color[channel] as u32 * (255 - alpha as u32);
+                    raw_data[pixel + channel] = ((color + background) / 255) as u8;
+                }
+                raw_data[pixel + 3] = 255;
+                filled += 1;
+            }
+        }
+    }
+    filled
+}
+
+/// sets the alpha of every base layer pixel to 255, the skin has to be 64 pixels wide
+pub fn make_base_layer_opaque(raw_data: &mut [u8], height: usize, model: &SkinModel) {
+    for part in PARTS {
+        for face in base_layer_faces(&part, height, model) {
+            for (x, y) in face_coordinates(&face) {
+                raw_data[pixel_index(x, y) + 3] = 255;
+            }
+        }
+    }
+}
+
+/// the base layer faces of the part that are on a 64 pixels wide skin with the given height,
+/// legacy (64x32) skins don't have a left arm and left leg
+pub fn base_layer_faces<'a>(part: &'a SkinPart, height: usize, model: &'a SkinModel)
+    -> impl Iterator<Item = OffsetAndDimension> + 'a {
+
+    FACES.into_iter()
+        .filter_map(move |face| texture_position_face(part, &SkinLayer::Bottom, &face, model))
+        .filter(move |face| face.y_offset + face.height <= height)
+}
+
+pub fn face_coordinates(face: &OffsetAndDimension) -> impl Iterator<Item = (usize, usize)> {
+    let (x_offset, y_offset, width, height) = (face.x_offset, face.y_offset, face.width, face.height);
+    (y_offset..y_offset + height).flat_map(move |y| (x_offset..x_offset + width).map(move |x| (x, y)))
+}
+
+/// the index of the first channel of the pixel on a 64 pixels wide skin
+pub fn pixel_index(x: usize, y: usize) -> usize {
+    (y * SKIN_WIDTH + x) * RGBA_CHANNELS
+}
+
+fn nearest(pixels: &[(usize, usize)], x: usize, y: usize) -> (usize, usize) {
+    *pixels.iter()
+        .min_by_key(|(other_x, other_y)| other_x.abs_diff(x).pow(2) + other_y.abs_diff(y).pow(2))
+        .unwrap()
+}
+
+fn rgb(data: &[u8], pixel: usize) -> [u8; 3] {
+    [data[pixel], data[pixel + 1], data[pixel + 2]]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::skin_convert::test_skins::{head_front, transparent_face};
+
+    use super::*;
+
+    #[test]
+    fn keeps_opaque_skins() {
+        let mut skin = STEVE_SKIN.clone();
+        for fill in [BaseLayerFill::DefaultSkin, BaseLayerFill::NearestNeighbour, BaseLayerFill::Black] {
+            assert_eq!(fill_base_layer(&mut skin, true, fill), 0);
+            assert_eq!(skin, *STEVE_SKIN);
+        }
+    }
+
+    #[test]
+    fn fills_with_black() {
+        let mut skin = transparent_face();
+        assert_eq!(fill_base_layer(&mut skin, true, BaseLayerFill::Black), 64);
+
+        for (x, y) in face_coordinates(&head_front()) {
+            assert_eq!(skin[pixel_index(x, y)..pixel_index(x, y) + 4], [0, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn fills_with_default_skin() {
+        let mut skin = transparent_face();
+        assert_eq!(fill_base_layer(&mut skin, true, BaseLayerFill::DefaultSkin), 64);
+        assert_eq!(skin, *STEVE_SKIN);
+    }
+
+    #[test]
+    fn fills_with_nearest_neighbour() {
+        let mut skin = transparent_face();
+        let face = head_front();
+        let corner = pixel_index(face.x_offset, face.y_offset);
+        skin[corner..corner + 4].copy_from_slice(&[10, 20, 30, 255]);
+
+        assert_eq!(fill_base_layer(&mut skin, true, BaseLayerFill::NearestNeighbour), 63);
+        for (x, y) in face_coordinates(&face) {
+            assert_eq!(skin[pixel_index(x, y)..pixel_index(x, y) + 4], [10, 20, 30, 255]);
+        }
+
+        // faces without opaque pixels use the default skin instead
+        let mut skin = transparent_face();
+        fill_base_layer(&mut skin, true, BaseLayerFill::NearestNeighbour);
+        assert_eq!(skin, *STEVE_SKIN);
+    }
+
+    #[test]
+    fn blends_semi_transparent_pixels() {
+        let mut skin = STEVE_SKIN.clone();
+        let face = head_front();
+        let pixel = pixel_index(face.x_offset, face.y_offset);
+        skin[pixel..pixel + 4].copy_from_slice(&[255, 255, 255, 51]);
+
+        assert_eq!(fill_base_layer(&mut skin, true, BaseLayerFill::Black), 1);
+        assert_eq!(skin[pixel..pixel + 4], [51, 51, 51, 255]);
+    }
+}
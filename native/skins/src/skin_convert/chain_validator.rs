@@ -1,65 +1,769 @@
-use jsonwebtokens::{Algorithm, AlgorithmID, Verifier};
-use rustler::ListIterator;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use jsonwebtokens::{Algorithm, AlgorithmID};
+use jsonwebtokens::raw::{decode_json_token_slice, split_token, TokenSlices, verify_signature_only};
+use lazy_static::lazy_static;
+use rayon::prelude::*;
+use rustler::{atoms, Encoder, Env, Term};
 use serde_json::Value;
 
-const MOJANG_PUBLIC_KEY: &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAECRXueJeTDqNRRgJi/vlRufByu/2G0i2Ebt6YMar5QX/R0DIIyrJMcUpruK4QveTfJSTp3Shlq4Gk34cD/4GUWwkv0DVuzeuB+tXija7HBxii03NHDbPAD0AKnLr2wdAp";
+use crate::skin_convert::key_source::{KeyCache, KeyError, StaticKey};
+
+pub const MOJANG_PUBLIC_KEY: &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAECRXueJeTDqNRRgJi/vlRufByu/2G0i2Ebt6YMar5QX/R0DIIyrJMcUpruK4QveTfJSTp3Shlq4Gk34cD/4GUWwkv0DVuzeuB+tXija7HBxii03NHDbPAD0AKnLr2wdAp";
+
+/// a chain contains the self-signed entry of the client, the entry signed by Mojang and the identity
+const MAX_CHAIN_LENGTH: usize = 3;
 
-pub fn validate_chain<'a>(chain_data: ListIterator<'a>, client_data: &'a str) -> Option<(Value, Value)> {
-    let verifier = Verifier::create().build().unwrap();
+/// the default amount of seconds that the clock of the client (and Mojang) may differ from ours
+pub const DEFAULT_CLOCK_SKEW: u64 = 60;
+
+static CLOCK_SKEW: AtomicU64 = AtomicU64::new(DEFAULT_CLOCK_SKEW);
+static OFFLINE_MODE: AtomicBool = AtomicBool::new(false);
+/// 0 means that chains don't have a max age
+static MAX_AGE: AtomicU64 = AtomicU64::new(0);
+
+lazy_static! {
+    static ref TRUSTED_KEYS: RwLock<Vec<String>> = RwLock::new(vec![String::from(MOJANG_PUBLIC_KEY)]);
+    /// only contains trusted keys, every chain is signed by one of them
+    static ref ROOT_KEY_CACHE: KeyCache = KeyCache::new(AlgorithmID::ES384);
+}
 
-    let mut current_key = create_key(MOJANG_PUBLIC_KEY);
-    let mut last_data = Value::Null;
-    let mut list_size: i32 = 0;
+/// changes the clock skew used by ChainOptions::current
+pub fn set_clock_skew(clock_skew: u64) {
+    CLOCK_SKEW.store(clock_skew, Ordering::Relaxed);
+}
+
+pub fn clock_skew() -> u64 {
+    CLOCK_SKEW.load(Ordering::Relaxed)
+}
+
+/// changes the max age used by ChainOptions::current, None only checks exp and nbf
+pub fn set_max_age(max_age: Option<u64>) {
+    MAX_AGE.store(max_age.unwrap_or(0), Ordering::Relaxed);
+}
 
-    let mut was_mojang = false;
-    let mut auth_completed = false;
+/// whether ChainOptions::current accepts the self-signed chains of servers in offline mode,
+/// this should only be enabled for testing
+pub fn set_offline_mode(offline_mode: bool) {
+    OFFLINE_MODE.store(offline_mode, Ordering::Relaxed);
+}
+
+/// replaces the root keys used by ChainOptions::current, which is only the Mojang key by default.
+/// Every key has to be a base64 encoded DER ES384 public key, otherwise the trusted keys aren't changed
+pub fn set_trusted_keys(keys: Vec<String>) -> Result<(), KeyError> {
+    if keys.is_empty() {
+        return Err(KeyError::MissingKeys);
+    }
+    for key in &keys {
+        StaticKey::from_der_base64(AlgorithmID::ES384, key)?;
+    }
+
+    *TRUSTED_KEYS.write().unwrap() = keys;
+    ROOT_KEY_CACHE.clear();
+    Ok(())
+}
+
+pub fn trusted_keys() -> Vec<String> {
+    TRUSTED_KEYS.read().unwrap().clone()
+}
+
+/// the current unix time in seconds
+pub fn current_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
 
-    for item in chain_data {
-        list_size += 1;
-        if list_size > 3 {
-            return None;
+#[derive(Debug, PartialEq, Eq)]
+pub enum ChainError {
+    /// the chain is empty or has more than MAX_CHAIN_LENGTH entries
+    InvalidLength,
+    /// an entry isn't a valid jwt
+    MalformedToken,
+    /// the header of an entry doesn't have an x5u
+    MissingX5u,
+    /// the x5u of an entry isn't the identityPublicKey of the previous entry
+    KeyMismatch,
+    /// the x5u or identityPublicKey isn't a valid ES384 public key
+    InvalidPublicKey,
+    /// an entry isn't signed by the key in its x5u
+    InvalidSignature,
+    Expired,
+    NotYetValid,
+    /// the iat of the last entry is longer ago than the max age
+    TooOld,
+    /// the same client data has been submitted before, see replay_guard
+    Replayed,
+    /// an entry that isn't the last entry doesn't have certificateAuthority set to true
+    NotCertificateAuthority,
+    MissingIdentityPublicKey,
+    /// none of the entries is signed by a trusted key
+    Untrusted,
+    /// the last entry doesn't have the extraData with the XUID, displayName and identity
+    MissingExtraData,
+    /// the client data isn't signed by the identityPublicKey of the last entry
+    InvalidClientData,
+}
+
+atoms! {
+    invalid_length,
+    malformed_token,
+    missing_x5u,
+    key_mismatch,
+    invalid_public_key,
+    invalid_signature,
+    expired,
+    not_yet_valid,
+    too_old,
+    replayed,
+    not_certificate_authority,
+    missing_identity_public_key,
+    untrusted,
+    missing_extra_data,
+    invalid_client_data,
+}
+
+impl Encoder for ChainError {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            ChainError::InvalidLength => invalid_length().encode(env),
+            ChainError::MalformedToken => malformed_token().encode(env),
+            ChainError::MissingX5u => missing_x5u().encode(env),
+            ChainError::KeyMismatch => key_mismatch().encode(env),
+            ChainError::InvalidPublicKey => invalid_public_key().encode(env),
+            ChainError::InvalidSignature => invalid_signature().encode(env),
+            ChainError::Expired => expired().encode(env),
+            ChainError::NotYetValid => not_yet_valid().encode(env),
+            ChainError::TooOld => too_old().encode(env),
+            ChainError::Replayed => replayed().encode(env),
+            ChainError::NotCertificateAuthority => not_certificate_authority().encode(env),
+            ChainError::MissingIdentityPublicKey => missing_identity_public_key().encode(env),
+            ChainError::Untrusted => untrusted().encode(env),
+            ChainError::MissingExtraData => missing_extra_data().encode(env),
+            ChainError::InvalidClientData => invalid_client_data().encode(env),
         }
+    }
+}
+
+pub struct ChainOptions {
+    /// the public keys (base64 encoded DER) of which at least one has to sign an entry
+    pub trusted_keys: Vec<String>,
+    /// the amount of seconds that exp and nbf may be off
+    pub clock_skew: u64,
+    /// the current unix time in seconds
+    pub now: u64,
+    /// accepts chains that only have a self-signed entry, like the chains of servers in offline mode
+    pub offline_mode: bool,
+    /// the max amount of seconds since the iat of the last entry, the identity is cached by the client for days
+    pub max_age: Option<u64>,
+    /// whether exp, nbf and the max age are checked, historical chains have expired long ago
+    pub check_time: bool,
+    /// caches the keys of the client and identity, trusted keys are always cached
+    pub key_cache: Option<Arc<KeyCache>>,
+}
 
-        if auth_completed {
-            return None;
+impl ChainOptions {
+    /// the options that the NIFs use
+    pub fn current() -> ChainOptions {
+        ChainOptions {
+            trusted_keys: trusted_keys(),
+            clock_skew: clock_skew(),
+            now: current_time(),
+            offline_mode: OFFLINE_MODE.load(Ordering::Relaxed),
+            max_age: Some(MAX_AGE.load(Ordering::Relaxed)).filter(|&max_age| max_age > 0),
+            check_time: true,
+            key_cache: None,
         }
+    }
+}
 
-        let data: &str = item.decode::<&str>().unwrap();
+pub struct ValidatedChain {
+    /// the claims of the last entry, which contain the extraData
+    pub identity_claims: Value,
+    pub client_claims: Value,
+    /// false when the chain is only self-signed, which is only accepted in offline mode
+    pub authenticated: bool,
+    /// the unix time after which the chain isn't accepted anymore (including the clock skew)
+    pub valid_until: u64,
+}
+
+struct Token<'a> {
+    header: Value,
+    claims: Value,
+    message: &'a str,
+    signature: &'a str,
+}
+
+/// validates the login chain and the client data that is signed by the key of the last entry in the chain.
+/// The first entry is self-signed, every next entry has to be signed by the identityPublicKey of the previous entry
+pub fn validate_chain(chain: &[&str], client_data: &str, options: &ChainOptions) -> Result<ValidatedChain, ChainError> {
+    if chain.is_empty() || chain.len() > MAX_CHAIN_LENGTH {
+        return Err(ChainError::InvalidLength);
+    }
 
-        let claims = verifier.verify(data, &current_key);
-        if let Ok(data) = claims {
-            if was_mojang {
-                auth_completed = true;
-            } else {
-                was_mojang = true;
-            }
+    let mut expected_key: Option<String> = None;
+    let mut trusted = false;
+    let mut identity_claims = Value::Null;
+    let mut valid_until = u64::MAX;
+
+    for (index, entry) in chain.iter().enumerate() {
+        let token = decode_token(entry)?;
+
+        let x5u = token.header["x5u"].as_str().ok_or(ChainError::MissingX5u)?;
+        if expected_key.as_ref().is_some_and(|expected| expected != x5u) {
+            return Err(ChainError::KeyMismatch);
+        }
+
+        let key = resolve_key(x5u, options)?;
+        verify_signature(&token, &key)?;
+        // a chain without exp would be valid forever, which would make captured chains replayable forever
+        let expires_at = time_claim(&token.claims, "exp")
+            .map_err(ChainError::from)?
+            .ok_or(ChainError::MalformedToken)?;
+        valid_until = valid_until.min(expires_at);
+        if options.check_time {
+            check_time(&token.claims, options.clock_skew, options.now)?;
+        }
 
-            last_data = data;
-            current_key = create_key(last_data["identityPublicKey"].as_str().unwrap());
-        } else if last_data != Value::Null {
-            return None;
+        if options.trusted_keys.iter().any(|key| key == x5u) {
+            trusted = true;
         }
+
+        let is_last = index == chain.len() - 1;
+        if !is_last && token.claims["certificateAuthority"] != Value::Bool(true) {
+            return Err(ChainError::NotCertificateAuthority);
+        }
+
+        let identity_public_key = token.claims["identityPublicKey"].as_str()
+            .ok_or(ChainError::MissingIdentityPublicKey)?;
+        expected_key = Some(String::from(identity_public_key));
+        identity_claims = token.claims;
+    }
+
+    // a chain that isn't trusted is only accepted in offline mode when it's nothing more than the self-signed entry
+    let accepted_offline = options.offline_mode && chain.len() == 1;
+    if !trusted && !accepted_offline {
+        return Err(ChainError::Untrusted);
     }
 
-    if !auth_completed {
-        return None;
+    if let Some(max_age) = options.max_age.filter(|_| options.check_time) {
+        let issued_at = time_claim(&identity_claims, "iat")
+            .map_err(ChainError::from)?
+            .ok_or(ChainError::MalformedToken)?;
+        if options.now > issued_at.saturating_add(max_age).saturating_add(options.clock_skew) {
+            return Err(ChainError::TooOld);
+        }
+        valid_until = valid_until.min(issued_at.saturating_add(max_age));
+    }
+
+    let extra_data = &identity_claims["extraData"];
+    if !extra_data["XUID"].is_string() || !extra_data["displayName"].is_string() || !extra_data["identity"].is_string() {
+        return Err(ChainError::MissingExtraData);
     }
 
-    let claims = verifier.verify(client_data, &current_key);
+    // the chain has at least one entry, so the expected key is always present
+    let client_token = decode_token(client_data).map_err(|_| ChainError::InvalidClientData)?;
+    resolve_key(&expected_key.unwrap(), options)
+        .and_then(|key| verify_signature(&client_token, &key))
+        .map_err(|_| ChainError::InvalidClientData)?;
+
+    Ok(ValidatedChain {
+        identity_claims,
+        client_claims: client_token.claims,
+        authenticated: trusted,
+        valid_until: valid_until.saturating_add(options.clock_skew),
+    })
+}
+
+/// validates client data that is signed by the given base64 encoded DER public key and returns its claims.
+/// Used for the token logins, where the token contains the public key of the client instead of a chain
+pub fn validate_client_data(client_data: &str, public_key: &str) -> Result<Value, ChainError> {
+    let client_token = decode_token(client_data).map_err(|_| ChainError::InvalidClientData)?;
+    StaticKey::from_der_base64(AlgorithmID::ES384, public_key)
+        .map_err(|_| ChainError::InvalidPublicKey)
+        .and_then(|key| verify_signature(&client_token, &key.0))
+        .map_err(|_| ChainError::InvalidClientData)?;
+    Ok(client_token.claims)
+}
+
+/// validates every (chain, client data) pair in parallel.
+/// Every key is parsed once per batch, so that the chains of the same player share their identity key
+pub fn validate_chains(pairs: &[(Vec<&str>, &str)], mut options: ChainOptions) -> Vec<Result<ValidatedChain, ChainError>> {
+    options.key_cache.get_or_insert_with(|| Arc::new(KeyCache::new(AlgorithmID::ES384)));
+    pairs.par_iter()
+        .map(|(chain, client_data)| validate_chain(chain, client_data, &options))
+        .collect()
+}
+
+fn decode_token(token: &str) -> Result<Token<'_>, ChainError> {
+    let TokenSlices { message, signature, header, claims } =
+        split_token(token).map_err(|_| ChainError::MalformedToken)?;
 
-    if claims.is_err() {
-        return None;
+    let header = decode_json_token_slice(header).map_err(|_| ChainError::MalformedToken)?;
+    let claims = decode_json_token_slice(claims).map_err(|_| ChainError::MalformedToken)?;
+    if !claims.is_object() {
+        return Err(ChainError::MalformedToken);
     }
 
-    let client_claims = claims.unwrap();
+    Ok(Token { header, claims, message, signature })
+}
+
+fn verify_signature(token: &Token, key: &Algorithm) -> Result<(), ChainError> {
+    verify_signature_only(&token.header, token.message, token.signature, key)
+        .map_err(|_| ChainError::InvalidSignature)
+}
+
+/// the verifier of the base64 encoded DER public key, from the cache when possible
+fn resolve_key(public_key: &str, options: &ChainOptions) -> Result<Arc<Algorithm>, ChainError> {
+    let key = if options.trusted_keys.iter().any(|key| key == public_key) {
+        ROOT_KEY_CACHE.get(public_key)
+    } else if let Some(key_cache) = &options.key_cache {
+        key_cache.get(public_key)
+    } else {
+        StaticKey::from_der_base64(AlgorithmID::ES384, public_key).map(|key| Arc::new(key.0))
+    };
+    key.map_err(|_| ChainError::InvalidPublicKey)
+}
+
+/// the reason why the time claims of a token aren't valid
+pub(crate) enum TimeError {
+    Expired,
+    NotYetValid,
+    /// exp or nbf isn't a valid unix time
+    Malformed,
+}
+
+impl From<TimeError> for ChainError {
+    fn from(err: TimeError) -> Self {
+        match err {
+            TimeError::Expired => ChainError::Expired,
+            TimeError::NotYetValid => ChainError::NotYetValid,
+            TimeError::Malformed => ChainError::MalformedToken,
+        }
+    }
+}
 
-    Some((last_data, client_claims))
+/// checks the exp and nbf claims when they're present, both can be off by the clock skew
+pub(crate) fn check_time(claims: &Value, clock_skew: u64, now: u64) -> Result<(), TimeError> {
+    if let Some(exp) = time_claim(claims, "exp")? {
+        if now > exp.saturating_add(clock_skew) {
+            return Err(TimeError::Expired);
+        }
+    }
+    if let Some(nbf) = time_claim(claims, "nbf")? {
+        if now.saturating_add(clock_skew) < nbf {
+            return Err(TimeError::NotYetValid);
+        }
+    }
+    Ok(())
 }
 
-fn create_key(pub_key: &str) -> Algorithm {
-    Algorithm::new_ecdsa_pem_verifier(AlgorithmID::ES384, create_key_from(pub_key).as_bytes()).unwrap()
+/// the value of a time claim, which is optional but has to be a valid unix time when present
+fn time_claim(claims: &Value, name: &str) -> Result<Option<u64>, TimeError> {
+    match &claims[name] {
+        Value::Null => Ok(None),
+        value => value.as_u64().map(Some).ok_or(TimeError::Malformed),
+    }
 }
 
-fn create_key_from(pub_key: &str) -> String {
-    vec!["-----BEGIN PUBLIC KEY-----", pub_key, "-----END PUBLIC KEY-----"].concat()
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::common::skin::STEVE_SKIN;
+    use crate::skin_convert::{convert_skin, ConvertResult, replay_guard};
+    use crate::skin_convert::skin_codec::{SKIN_HEIGHT, SKIN_WIDTH};
+    use crate::skin_convert::test_keys::TestKey;
+
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const CLOCK_SKEW: u64 = 60;
+    const MAX_AGE: u64 = 3600;
+
+    /// a chain like the one a Bedrock client sends, signed by throwaway keys
+    struct TestChain {
+        client: TestKey,
+        mojang: TestKey,
+        identity: TestKey,
+        claims: [Value; 3],
+    }
+
+    impl TestChain {
+        fn new() -> TestChain {
+            TestChain::at(NOW)
+        }
+
+        /// a chain that is valid at the given time
+        fn at(now: u64) -> TestChain {
+            let (client, mojang, identity) = (TestKey::es384(), TestKey::es384(), TestKey::es384());
+            let claims = [
+                json!({
+                    "identityPublicKey": mojang.public_key(),
+                    "certificateAuthority": true,
+                    "nbf": now - 60,
+                    "exp": now + 3600,
+                }),
+                json!({
+                    "identityPublicKey": identity.public_key(),
+                    "certificateAuthority": true,
+                    "nbf": now - 60,
+                    "exp": now + 3600,
+                }),
+                json!({
+                    "identityPublicKey": client.public_key(),
+                    "extraData": {
+                        "XUID": "2535400000000000",
+                        "displayName": "Tim203",
+                        "identity": "00000000-0000-0000-0009-01f64f65c7c3",
+                    },
+                    "iat": now - 60,
+                    "nbf": now - 60,
+                    "exp": now + 3600,
+                }),
+            ];
+            TestChain { client, mojang, identity, claims }
+        }
+
+        fn sign(&self) -> Vec<String> {
+            vec![
+                self.client.sign_with_x5u(&self.client.public_key(), &self.claims[0]),
+                self.mojang.sign_with_x5u(&self.mojang.public_key(), &self.claims[1]),
+                self.identity.sign_with_x5u(&self.identity.public_key(), &self.claims[2]),
+            ]
+        }
+
+        fn client_data(&self) -> String {
+            self.client.sign_with_x5u(&self.client.public_key(), &json!({ "SkinId": "test" }))
+        }
+
+        fn validate(&self, chain: &[String], client_data: &str) -> Result<ValidatedChain, ChainError> {
+            let chain: Vec<&str> = chain.iter().map(String::as_str).collect();
+            let options = ChainOptions {
+                trusted_keys: vec![self.mojang.public_key()],
+                clock_skew: CLOCK_SKEW,
+                now: NOW,
+                offline_mode: false,
+                max_age: Some(MAX_AGE),
+                check_time: true,
+                key_cache: None,
+            };
+            validate_chain(&chain, client_data, &options)
+        }
+
+        fn error(&self) -> Option<ChainError> {
+            self.validate(&self.sign(), &self.client_data()).err()
+        }
+    }
+
+    #[test]
+    fn accepts_valid_chain() {
+        let chain = TestChain::new();
+        let result = chain.validate(&chain.sign(), &chain.client_data()).ok().unwrap();
+
+        assert_eq!(result.identity_claims["extraData"]["displayName"], "Tim203");
+        assert_eq!(result.client_claims["SkinId"], "test");
+        assert!(result.authenticated);
+    }
+
+    #[test]
+    fn rejects_invalid_length() {
+        let chain = TestChain::new();
+        let client_data = chain.client_data();
+        assert_eq!(chain.validate(&[], &client_data).err(), Some(ChainError::InvalidLength));
+
+        let mut entries = chain.sign();
+        entries.push(entries[2].clone());
+        assert_eq!(chain.validate(&entries, &client_data).err(), Some(ChainError::InvalidLength));
+    }
+
+    #[test]
+    fn rejects_malformed_token() {
+        let chain = TestChain::new();
+        let mut entries = chain.sign();
+        entries[1] = String::from("not.a.jwt");
+        assert_eq!(chain.validate(&entries, &chain.client_data()).err(), Some(ChainError::MalformedToken));
+    }
+
+    #[test]
+    fn rejects_missing_x5u() {
+        let chain = TestChain::new();
+        let mut entries = chain.sign();
+        entries[0] = chain.client.sign(&json!({ "alg": "ES384" }), &chain.claims[0]);
+        assert_eq!(chain.validate(&entries, &chain.client_data()).err(), Some(ChainError::MissingX5u));
+    }
+
+    #[test]
+    fn rejects_key_mismatch() {
+        let mut chain = TestChain::new();
+        // the self-signed entry delegates to a different key than the key that signed the next entry
+        chain.claims[0]["identityPublicKey"] = Value::from(TestKey::es384().public_key());
+        assert_eq!(chain.error(), Some(ChainError::KeyMismatch));
+    }
+
+    #[test]
+    fn rejects_invalid_public_key() {
+        let chain = TestChain::new();
+        let mut entries = chain.sign();
+        entries[0] = chain.client.sign_with_x5u("invalid", &chain.claims[0]);
+        assert_eq!(chain.validate(&entries, &chain.client_data()).err(), Some(ChainError::InvalidPublicKey));
+    }
+
+    #[test]
+    fn rejects_invalid_signature() {
+        let chain = TestChain::new();
+        let mut entries = chain.sign();
+        // claims to be signed by Mojang, but isn't
+        entries[1] = chain.client.sign_with_x5u(&chain.mojang.public_key(), &chain.claims[1]);
+        assert_eq!(chain.validate(&entries, &chain.client_data()).err(), Some(ChainError::InvalidSignature));
+    }
+
+    #[test]
+    fn rejects_expired() {
+        let mut chain = TestChain::new();
+        chain.claims[1]["exp"] = Value::from(NOW - CLOCK_SKEW);
+        assert_eq!(chain.error(), None);
+
+        chain.claims[1]["exp"] = Value::from(NOW - CLOCK_SKEW - 1);
+        assert_eq!(chain.error(), Some(ChainError::Expired));
+    }
+
+    #[test]
+    fn rejects_not_yet_valid() {
+        let mut chain = TestChain::new();
+        chain.claims[2]["nbf"] = Value::from(NOW + CLOCK_SKEW);
+        assert_eq!(chain.error(), None);
+
+        chain.claims[2]["nbf"] = Value::from(NOW + CLOCK_SKEW + 1);
+        assert_eq!(chain.error(), Some(ChainError::NotYetValid));
+    }
+
+    #[test]
+    fn rejects_missing_exp() {
+        let mut chain = TestChain::new();
+        chain.claims[0].as_object_mut().unwrap().remove("exp");
+        assert_eq!(chain.error(), Some(ChainError::MalformedToken));
+    }
+
+    #[test]
+    fn rejects_too_old() {
+        let mut chain = TestChain::new();
+        chain.claims[2]["iat"] = Value::from(NOW - MAX_AGE - CLOCK_SKEW);
+        assert_eq!(chain.error(), None);
+
+        chain.claims[2]["iat"] = Value::from(NOW - MAX_AGE - CLOCK_SKEW - 1);
+        assert_eq!(chain.error(), Some(ChainError::TooOld));
+
+        chain.claims[2].as_object_mut().unwrap().remove("iat");
+        assert_eq!(chain.error(), Some(ChainError::MalformedToken));
+    }
+
+    #[test]
+    fn accepts_day_old_identity_by_default() {
+        let mut chain = TestChain::new();
+        // the client caches its identity for days
+        chain.claims[2]["iat"] = Value::from(NOW - 24 * 60 * 60);
+        chain.claims[2]["nbf"] = Value::from(NOW - 24 * 60 * 60);
+
+        let entries = chain.sign();
+        let entries: Vec<&str> = entries.iter().map(String::as_str).collect();
+        let options = ChainOptions { trusted_keys: vec![chain.mojang.public_key()], now: NOW, ..ChainOptions::current() };
+        assert!(validate_chain(&entries, &chain.client_data(), &options).is_ok());
+    }
+
+    #[test]
+    fn valid_until_earliest_expiry() {
+        let mut chain = TestChain::new();
+        chain.claims[1]["exp"] = Value::from(NOW + 600);
+        let result = chain.validate(&chain.sign(), &chain.client_data()).ok().unwrap();
+        assert_eq!(result.valid_until, NOW + 600 + CLOCK_SKEW);
+
+        // the max age ends before the identity expires
+        chain.claims[1]["exp"] = Value::from(NOW + 3600);
+        chain.claims[2]["iat"] = Value::from(NOW - MAX_AGE + 120);
+        let result = chain.validate(&chain.sign(), &chain.client_data()).ok().unwrap();
+        assert_eq!(result.valid_until, NOW + 120 + CLOCK_SKEW);
+    }
+
+    #[test]
+    fn validates_client_data_with_public_key() {
+        let chain = TestChain::new();
+        let claims = validate_client_data(&chain.client_data(), &chain.client.public_key()).ok().unwrap();
+        assert_eq!(claims["SkinId"], "test");
+
+        let other_key = TestKey::es384().public_key();
+        assert_eq!(validate_client_data(&chain.client_data(), &other_key).err(), Some(ChainError::InvalidClientData));
+        assert_eq!(validate_client_data("not.a.jwt", &other_key).err(), Some(ChainError::InvalidClientData));
+    }
+
+    #[test]
+    fn rejects_invalid_time_claim() {
+        let mut chain = TestChain::new();
+        chain.claims[2]["exp"] = Value::from("tomorrow");
+        assert_eq!(chain.error(), Some(ChainError::MalformedToken));
+    }
+
+    #[test]
+    fn rejects_not_certificate_authority() {
+        let mut chain = TestChain::new();
+        chain.claims[1]["certificateAuthority"] = Value::from(false);
+        assert_eq!(chain.error(), Some(ChainError::NotCertificateAuthority));
+
+        chain.claims[1].as_object_mut().unwrap().remove("certificateAuthority");
+        assert_eq!(chain.error(), Some(ChainError::NotCertificateAuthority));
+    }
+
+    #[test]
+    fn rejects_missing_identity_public_key() {
+        let mut chain = TestChain::new();
+        chain.claims[2].as_object_mut().unwrap().remove("identityPublicKey");
+        assert_eq!(chain.error(), Some(ChainError::MissingIdentityPublicKey));
+    }
+
+    #[test]
+    fn rejects_untrusted() {
+        let mut chain = TestChain::new();
+        // the same chain, but signed by a key that isn't trusted instead of Mojang
+        chain.mojang = TestKey::es384();
+        chain.claims[0]["identityPublicKey"] = Value::from(chain.mojang.public_key());
+
+        let entries = chain.sign();
+        let chain_entries: Vec<&str> = entries.iter().map(String::as_str).collect();
+        let mut options = ChainOptions {
+            trusted_keys: vec![String::from(MOJANG_PUBLIC_KEY)],
+            clock_skew: CLOCK_SKEW,
+            now: NOW,
+            offline_mode: false,
+            max_age: None,
+            check_time: true,
+            key_cache: None,
+        };
+        assert_eq!(
+            validate_chain(&chain_entries, &chain.client_data(), &options).err(),
+            Some(ChainError::Untrusted)
+        );
+
+        // offline mode only accepts chains that are nothing more than the self-signed entry
+        options.offline_mode = true;
+        assert_eq!(
+            validate_chain(&chain_entries, &chain.client_data(), &options).err(),
+            Some(ChainError::Untrusted)
+        );
+    }
+
+    #[test]
+    fn accepts_self_signed_chain_in_offline_mode() {
+        let mut chain = TestChain::new();
+        // offline servers send a single self-signed entry that has the extraData
+        chain.claims[0] = chain.claims[2].clone();
+        chain.claims[0]["identityPublicKey"] = Value::from(chain.client.public_key());
+
+        let entries = vec![chain.client.sign_with_x5u(&chain.client.public_key(), &chain.claims[0])];
+        assert_eq!(chain.validate(&entries, &chain.client_data()).err(), Some(ChainError::Untrusted));
+
+        let entries: Vec<&str> = entries.iter().map(String::as_str).collect();
+        let options = ChainOptions {
+            trusted_keys: vec![chain.mojang.public_key()],
+            clock_skew: CLOCK_SKEW,
+            now: NOW,
+            offline_mode: true,
+            max_age: None,
+            check_time: true,
+            key_cache: None,
+        };
+        let result = validate_chain(&entries, &chain.client_data(), &options).ok().unwrap();
+        assert!(!result.authenticated);
+        assert_eq!(result.identity_claims["extraData"]["displayName"], "Tim203");
+    }
+
+    #[test]
+    fn rejects_missing_extra_data() {
+        let mut chain = TestChain::new();
+        chain.claims[2]["extraData"].as_object_mut().unwrap().remove("XUID");
+        assert_eq!(chain.error(), Some(ChainError::MissingExtraData));
+
+        chain.claims[2].as_object_mut().unwrap().remove("extraData");
+        assert_eq!(chain.error(), Some(ChainError::MissingExtraData));
+    }
+
+    #[test]
+    fn rejects_invalid_client_data() {
+        let chain = TestChain::new();
+        let entries = chain.sign();
+
+        let other_key = TestKey::es384();
+        let other_client = other_key.sign_with_x5u(&other_key.public_key(), &json!({ "SkinId": "test" }));
+        assert_eq!(chain.validate(&entries, &other_client).err(), Some(ChainError::InvalidClientData));
+        assert_eq!(chain.validate(&entries, "invalid").err(), Some(ChainError::InvalidClientData));
+    }
+
+    /// the only test that changes the global trusted keys, the other tests pass their own options.
+    /// Goes through a login like validate_and_convert handles it, with a chain signed by a throwaway root key
+    #[test]
+    fn trusts_configured_keys() {
+        let chain = TestChain::at(current_time());
+        let entries = chain.sign();
+        let entries: Vec<&str> = entries.iter().map(String::as_str).collect();
+        let client_data = chain.client.sign_with_x5u(&chain.client.public_key(), &json!({
+            "SkinImageWidth": SKIN_WIDTH,
+            "SkinImageHeight": SKIN_HEIGHT,
+            "SkinData": base64::encode(&*STEVE_SKIN),
+            "SkinResourcePatch": base64::encode(r#"{"geometry":{"default":"geometry.humanoid.custom"}}"#),
+            "SkinGeometryData": "bnVsbAo=",
+        }));
+
+        assert_eq!(validate_chain(&entries, &client_data, &ChainOptions::current()).err(), Some(ChainError::Untrusted));
+
+        assert_eq!(set_trusted_keys(vec![]), Err(KeyError::MissingKeys));
+        assert_eq!(set_trusted_keys(vec![chain.mojang.public_key(), String::from("invalid")]), Err(KeyError::InvalidKey));
+        assert_eq!(trusted_keys(), [MOJANG_PUBLIC_KEY]);
+
+        set_trusted_keys(vec![String::from(MOJANG_PUBLIC_KEY), chain.mojang.public_key()]).unwrap();
+        let result = validate_chain(&entries, &client_data, &ChainOptions::current());
+        // rotating back to the Mojang key takes effect right away
+        set_trusted_keys(vec![String::from(MOJANG_PUBLIC_KEY)]).unwrap();
+        assert_eq!(validate_chain(&entries, &client_data, &ChainOptions::current()).err(), Some(ChainError::Untrusted));
+
+        let validated = result.ok().unwrap();
+        assert!(validated.authenticated);
+        assert_eq!(validated.identity_claims["extraData"]["XUID"], "2535400000000000");
+
+        let now = current_time();
+        assert_eq!(replay_guard::check_replay(&client_data, validated.valid_until, now), Ok(()));
+        assert_eq!(replay_guard::check_replay(&client_data, validated.valid_until, now), Err(ChainError::Replayed));
+
+        let ConvertResult::Success(_, is_steve, _) = convert_skin(&validated.client_claims) else {
+            panic!("failed to convert the skin of the login");
+        };
+        assert!(is_steve);
+    }
+
+    #[test]
+    fn validates_batch() {
+        let (chain, expired) = (TestChain::new(), TestChain::at(NOW - 7200));
+        let (entries, client_data) = (chain.sign(), chain.client_data());
+        let (expired_entries, expired_client_data) = (expired.sign(), expired.client_data());
+
+        let pairs: Vec<(Vec<&str>, &str)> = vec![
+            (entries.iter().map(String::as_str).collect(), &client_data),
+            (entries.iter().map(String::as_str).collect(), "invalid"),
+            (expired_entries.iter().map(String::as_str).collect(), &expired_client_data),
+        ];
+        let options = |check_time| ChainOptions {
+            trusted_keys: vec![chain.mojang.public_key(), expired.mojang.public_key()],
+            clock_skew: CLOCK_SKEW,
+            now: NOW,
+            offline_mode: false,
+            max_age: None,
+            check_time,
+            key_cache: None,
+        };
+
+        let errors: Vec<Option<ChainError>> = validate_chains(&pairs, options(true)).into_iter().map(Result::err).collect();
+        assert_eq!(errors, [None, Some(ChainError::InvalidClientData), Some(ChainError::Expired)]);
+
+        // historical chains have expired, but their signatures are still valid
+        let errors: Vec<Option<ChainError>> = validate_chains(&pairs, options(false)).into_iter().map(Result::err).collect();
+        assert_eq!(errors, [None, Some(ChainError::InvalidClientData), None]);
+    }
+}
@@ -1,7 +1,6 @@
 use std::ops::Deref;
 use std::primitive;
 use std::str::from_utf8;
-use std::sync::MutexGuard;
 use json::JsonValue;
 
 use serde_json::Value;
@@ -257,7 +256,7 @@ fn convert_geometry(skin_data: &[u8], mut skin_width: usize, client_claims: &Val
     Ok((new_vec, skin_model))
 }
 
-fn get_correct_entry<'a>(format_version: &'a str, geometry_data: &'a JsonValue, geometry_name: &'a str) -> Result<(&'a JsonValue, usize, usize), &'static str> {
+pub(crate) fn get_correct_entry<'a>(format_version: &'a str, geometry_data: &'a JsonValue, geometry_name: &'a str) -> Result<(&'a JsonValue, usize, usize), &'static str> {
     match format_version {
         "1.8.0" => {
             let geometry_data = &geometry_data[geometry_name];
@@ -564,7 +563,7 @@ fn is_bottom_arm(bone_name: &str) -> bool {
     section.1 == SkinLayer::Bottom && section.0 == &SkinPart::ArmLeft || section.0 == &SkinPart::ArmRight
 }
 
-fn bone_name_to_skin_section(bone_name: &primitive::str) -> Option<SkinSection> {
+pub(crate) fn bone_name_to_skin_section(bone_name: &primitive::str) -> Option<SkinSection<'_>> {
     match bone_name {
         "head" => Some(SkinSection(&SkinPart::Head, SkinLayer::Bottom)),
         "hat" => Some(SkinSection(&SkinPart::Head, SkinLayer::Top)),
@@ -589,7 +588,7 @@ fn get_texture_position(bone_name: &primitive::str) -> Option<OffsetAndDimension
 
 //region skin debugger
 #[cfg(feature = "build-binary")]
-fn get_instance<'a>() -> MutexGuard<'a, crate::gui::skin_convert::SkinConvertData> {
+fn get_instance<'a>() -> std::sync::MutexGuard<'a, crate::gui::skin_convert::SkinConvertData> {
     crate::gui::skin_convert::INSTANCE.lock().unwrap()
 }
 
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use jsonwebtokens::{Algorithm, AlgorithmID};
+use rustler::{atoms, Encoder, Env, Term};
+use serde_json::Value;
+
+/// the DER encoded start of a public key, followed by the uncompressed point of the curve
+const P256_KEY_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01,
+    0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00
+];
+pub(crate) const P384_KEY_PREFIX: [u8; 23] = [
+    0x30, 0x76, 0x30, 0x10, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02,
+    0x01, 0x06, 0x05, 0x2b, 0x81, 0x04, 0x00, 0x22, 0x03, 0x62, 0x00
+];
+
+/// the default amount of key sets that are active at the same time while rotating
+pub const DEFAULT_ACTIVE_KEY_SETS: usize = 2;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyError {
+    InvalidJson,
+    /// the document doesn't have any key that is supported
+    MissingKeys,
+    InvalidKey,
+}
+
+atoms! {
+    invalid_json,
+    missing_keys,
+    invalid_key,
+}
+
+impl Encoder for KeyError {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            KeyError::InvalidJson => invalid_json().encode(env),
+            KeyError::MissingKeys => missing_keys().encode(env),
+            KeyError::InvalidKey => invalid_key().encode(env),
+        }
+    }
+}
+
+/// provides the keys that a token can be signed with
+pub trait KeySource: Send + Sync {
+    /// the keys that can have signed a token with the given key id (kid).
+    /// Tokens without a key id can be signed by any key
+    fn keys(&self, key_id: Option<&str>) -> Vec<&Algorithm>;
+}
+
+/// a single key that is always used, regardless of the key id
+pub struct StaticKey(pub Algorithm);
+
+impl StaticKey {
+    /// creates a key from a base64 encoded DER public key, like the x5u of a Bedrock login chain
+    pub fn from_der_base64(algorithm: AlgorithmID, public_key: &str) -> Result<StaticKey, KeyError> {
+        let pem = ["-----BEGIN PUBLIC KEY-----", public_key, "-----END PUBLIC KEY-----"].concat();
+        Algorithm::new_ecdsa_pem_verifier(algorithm, pem.as_bytes())
+            .map(StaticKey)
+            .map_err(|_| KeyError::InvalidKey)
+    }
+}
+
+impl KeySource for StaticKey {
+    fn keys(&self, _key_id: Option<&str>) -> Vec<&Algorithm> {
+        vec![&self.0]
+    }
+}
+
+/// base64 encoded DER public keys that have been parsed before, so that the verifier is only created once per key
+pub struct KeyCache {
+    algorithm: AlgorithmID,
+    keys: RwLock<HashMap<String, Arc<Algorithm>>>,
+}
+
+impl KeyCache {
+    pub fn new(algorithm: AlgorithmID) -> KeyCache {
+        KeyCache { algorithm, keys: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn get(&self, public_key: &str) -> Result<Arc<Algorithm>, KeyError> {
+        if let Some(key) = self.keys.read().unwrap().get(public_key) {
+            return Ok(key.clone());
+        }
+
+        // parsed without holding the lock, another thread might have inserted the same key in the meantime
+        let key = Arc::new(StaticKey::from_der_base64(self.algorithm, public_key)?.0);
+        Ok(self.keys.write().unwrap().entry(String::from(public_key)).or_insert(key).clone())
+    }
+
+    pub fn clear(&self) {
+        self.keys.write().unwrap().clear();
+    }
+}
+
+/// the keys of a JSON Web Key Set, like the one of an OpenID discovery endpoint
+pub struct Jwks {
+    keys: Vec<JwksKey>,
+}
+
+/// the key id isn't set on the verifier, because a verifier with a key id rejects every token without one
+struct JwksKey {
+    key_id: Option<String>,
+    algorithm: Algorithm,
+}
+
+impl Jwks {
+    /// parses the JWKS document, keys that aren't used for signatures or that aren't supported are ignored
+    pub fn parse(document: &str) -> Result<Jwks, KeyError> {
+        let document: Value = serde_json::from_str(document).map_err(|_| KeyError::InvalidJson)?;
+        let keys = document["keys"].as_array().ok_or(KeyError::MissingKeys)?;
+
+        let mut parsed = Vec::with_capacity(keys.len());
+        for key in keys {
+            if key["use"].as_str().is_some_and(|key_use| key_use != "sig") {
+                continue;
+            }
+            if let Some(algorithm) = parse_jwk(key)? {
+                let key_id = key["kid"].as_str().map(String::from);
+                parsed.push(JwksKey { key_id, algorithm });
+            }
+        }
+
+        if parsed.is_empty() {
+            return Err(KeyError::MissingKeys);
+        }
+        Ok(Jwks { keys: parsed })
+    }
+}
+
+impl KeySource for Jwks {
+    fn keys(&self, key_id: Option<&str>) -> Vec<&Algorithm> {
+        self.keys.iter()
+            .filter(|key| key_id.is_none() || key.key_id.is_none() || key.key_id.as_deref() == key_id)
+            .map(|key| &key.algorithm)
+            .collect()
+    }
+}
+
+/// multiple key sets that are active at the same time, so that tokens signed with the previous key set
+/// are still valid after a new key set has been published
+pub struct RotatingKeys {
+    /// the newest key set is the last one
+    key_sets: Vec<Box<dyn KeySource>>,
+    max_active: usize,
+}
+
+impl RotatingKeys {
+    pub fn new(max_active: usize) -> RotatingKeys {
+        RotatingKeys { key_sets: Vec::new(), max_active: max_active.max(1) }
+    }
+
+    /// makes the key set active, the oldest key set is removed when there are too many active key sets
+    pub fn rotate(&mut self, key_set: impl KeySource + 'static) {
+        self.key_sets.push(Box::new(key_set));
+        if self.key_sets.len() > self.max_active {
+            self.key_sets.remove(0);
+        }
+    }
+}
+
+impl KeySource for RotatingKeys {
+    fn keys(&self, key_id: Option<&str>) -> Vec<&Algorithm> {
+        // the newest keys are the most likely to have signed the token
+        self.key_sets.iter().rev()
+            .flat_map(|key_set| key_set.keys(key_id))
+            .collect()
+    }
+}
+
+/// returns None when the key type, curve or algorithm isn't supported
+fn parse_jwk(key: &Value) -> Result<Option<Algorithm>, KeyError> {
+    let algorithm = match key["kty"].as_str() {
+        Some("RSA") => {
+            let algorithm = match key["alg"].as_str().unwrap_or("RS256") {
+                "RS256" => AlgorithmID::RS256,
+                "RS384" => AlgorithmID::RS384,
+                "RS512" => AlgorithmID::RS512,
+                _ => return Ok(None),
+            };
+            let n = key["n"].as_str().ok_or(KeyError::InvalidKey)?;
+            let e = key["e"].as_str().ok_or(KeyError::InvalidKey)?;
+            Algorithm::new_rsa_n_e_b64_verifier(algorithm, n, e).map_err(|_| KeyError::InvalidKey)?
+        }
+        Some("EC") => {
+            let (algorithm, prefix): (_, &[u8]) = match key["crv"].as_str() {
+                Some("P-256") => (AlgorithmID::ES256, &P256_KEY_PREFIX),
+                Some("P-384") => (AlgorithmID::ES384, &P384_KEY_PREFIX),
+                _ => return Ok(None),
+            };
+            let x = decode_coordinate(&key["x"])?;
+            let y = decode_coordinate(&key["y"])?;
+
+            let der = [prefix, &[0x04], &x, &y].concat();
+            StaticKey::from_der_base64(algorithm, &base64::encode(der))?.0
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(algorithm))
+}
+
+fn decode_coordinate(coordinate: &Value) -> Result<Vec<u8>, KeyError> {
+    let coordinate = coordinate.as_str().ok_or(KeyError::InvalidKey)?;
+    base64::decode_config(coordinate, base64::URL_SAFE_NO_PAD).map_err(|_| KeyError::InvalidKey)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::skin_convert::test_keys::TestKey;
+
+    use super::*;
+
+    /// the key ids of the keys that can have signed a token with the given key id
+    fn key_ids<'a>(jwks: &'a Jwks, key_id: Option<&str>) -> Vec<Option<&'a str>> {
+        let selected = jwks.keys(key_id);
+        jwks.keys.iter()
+            .filter(|key| selected.iter().any(|&algorithm| std::ptr::eq(algorithm, &key.algorithm)))
+            .map(|key| key.key_id.as_deref())
+            .collect()
+    }
+
+    #[test]
+    fn parses_ec_keys() {
+        let jwks = json!({ "keys": [TestKey::es256().jwk("first"), TestKey::es384().jwk("second")] });
+        let jwks = Jwks::parse(&jwks.to_string()).unwrap();
+
+        assert_eq!(key_ids(&jwks, None), [Some("first"), Some("second")]);
+        assert_eq!(key_ids(&jwks, Some("second")), [Some("second")]);
+        assert!(jwks.keys(Some("third")).is_empty());
+    }
+
+    #[test]
+    fn keys_without_key_id_match_every_token() {
+        let mut without_key_id = TestKey::es256().jwk("unused");
+        without_key_id.as_object_mut().unwrap().remove("kid");
+        let jwks = json!({ "keys": [TestKey::es256().jwk("first"), without_key_id] });
+        let jwks = Jwks::parse(&jwks.to_string()).unwrap();
+
+        assert_eq!(key_ids(&jwks, Some("first")), [Some("first"), None]);
+        assert_eq!(key_ids(&jwks, Some("second")), [None]);
+    }
+
+    #[test]
+    fn ignores_unsupported_keys() {
+        let mut encryption_key = TestKey::es256().jwk("encryption");
+        encryption_key["use"] = json!("enc");
+        let jwks = json!({ "keys": [
+            { "kty": "OKP", "crv": "Ed25519", "kid": "okp", "x": "" },
+            { "kty": "EC", "crv": "P-521", "kid": "p521", "x": "", "y": "" },
+            encryption_key,
+            TestKey::es256().jwk("supported"),
+        ] });
+
+        let jwks = Jwks::parse(&jwks.to_string()).unwrap();
+        assert_eq!(key_ids(&jwks, None), [Some("supported")]);
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert_eq!(Jwks::parse("{").err(), Some(KeyError::InvalidJson));
+        assert_eq!(Jwks::parse("{}").err(), Some(KeyError::MissingKeys));
+        assert_eq!(Jwks::parse(r#"{"keys":[{"kty":"OKP"}]}"#).err(), Some(KeyError::MissingKeys));
+
+        let mut invalid_key = TestKey::es256().jwk("invalid");
+        invalid_key["x"] = json!("not base64!");
+        assert_eq!(Jwks::parse(&json!({ "keys": [invalid_key] }).to_string()).err(), Some(KeyError::InvalidKey));
+    }
+
+    #[test]
+    fn caches_parsed_keys() {
+        let cache = KeyCache::new(AlgorithmID::ES384);
+        let public_key = TestKey::es384().public_key();
+
+        let key = cache.get(&public_key).unwrap();
+        assert!(Arc::ptr_eq(&key, &cache.get(&public_key).unwrap()));
+        assert!(cache.get("invalid").is_err());
+
+        cache.clear();
+        assert!(!Arc::ptr_eq(&key, &cache.get(&public_key).unwrap()));
+    }
+
+    #[test]
+    fn rotation_keeps_max_active_key_sets() {
+        let mut rotating = RotatingKeys::new(2);
+        for key_id in ["first", "second", "third"] {
+            let jwks = json!({ "keys": [TestKey::es256().jwk(key_id)] });
+            rotating.rotate(Jwks::parse(&jwks.to_string()).unwrap());
+        }
+
+        assert_eq!(rotating.keys(None).len(), 2);
+        assert_eq!(rotating.keys(Some("third")).len(), 1);
+        assert_eq!(rotating.keys(Some("second")).len(), 1);
+        assert!(rotating.keys(Some("first")).is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use rustler::{atoms, Encoder, Env, Term};
+use rustler::types::map::map_new;
+use serde_json::Value;
+
+use crate::skin_convert::chain_validator::ValidatedChain;
+
+atoms! {
+    xuid,
+    display_name,
+    identity,
+    title_id,
+    sandbox_id,
+    identity_public_key,
+    issued_at,
+    device_os,
+    device_model,
+    language_code,
+    game_version,
+    platform_online_id,
+    authenticated,
+}
+
+/// the claims of a validated login chain and client data that describe the player and their device.
+/// Claims that the client didn't send are None
+#[derive(Debug, PartialEq, Eq)]
+pub struct LoginIdentity {
+    pub xuid: String,
+    pub display_name: String,
+    /// the uuid that Bedrock uses for the player
+    pub identity: String,
+    pub title_id: Option<String>,
+    pub sandbox_id: Option<String>,
+    /// the key that the client data is signed with
+    pub identity_public_key: String,
+    /// unix time in seconds
+    pub issued_at: Option<i64>,
+    /// the DeviceOS enum of Bedrock, e.g. 1 for Android and 7 for Windows 10
+    pub device_os: Option<i64>,
+    pub device_model: Option<String>,
+    pub language_code: Option<String>,
+    pub game_version: Option<String>,
+    pub platform_online_id: Option<String>,
+    /// false when the chain is only self-signed, see ChainOptions::offline_mode
+    pub authenticated: bool,
+}
+
+impl From<&ValidatedChain> for LoginIdentity {
+    fn from(chain: &ValidatedChain) -> Self {
+        let identity_claims = &chain.identity_claims;
+        let extra_data = &identity_claims["extraData"];
+        let client_claims = &chain.client_claims;
+
+        // the validator made sure that the XUID, displayName, identity and identityPublicKey are present
+        LoginIdentity {
+            xuid: string_claim(&extra_data["XUID"]).unwrap_or_default(),
+            display_name: string_claim(&extra_data["displayName"]).unwrap_or_default(),
+            identity: string_claim(&extra_data["identity"]).unwrap_or_default(),
+            title_id: string_claim(&extra_data["titleId"]),
+            sandbox_id: string_claim(&extra_data["sandboxId"]),
+            identity_public_key: string_claim(&identity_claims["identityPublicKey"]).unwrap_or_default(),
+            issued_at: identity_claims["iat"].as_i64(),
+            device_os: client_claims["DeviceOS"].as_i64(),
+            device_model: string_claim(&client_claims["DeviceModel"]),
+            language_code: string_claim(&client_claims["LanguageCode"]),
+            game_version: string_claim(&client_claims["GameVersion"]),
+            // an empty string when the platform doesn't have an online id
+            platform_online_id: string_claim(&client_claims["PlatformOnlineId"]).filter(|id| !id.is_empty()),
+            authenticated: chain.authenticated,
+        }
+    }
+}
+
+impl Encoder for LoginIdentity {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        map_new(env)
+            .map_put(xuid().encode(env), self.xuid.encode(env)).unwrap()
+            .map_put(display_name().encode(env), self.display_name.encode(env)).unwrap()
+            .map_put(identity().encode(env), self.identity.encode(env)).unwrap()
+            .map_put(title_id().encode(env), self.title_id.encode(env)).unwrap()
+            .map_put(sandbox_id().encode(env), self.sandbox_id.encode(env)).unwrap()
+            .map_put(identity_public_key().encode(env), self.identity_public_key.encode(env)).unwrap()
+            .map_put(issued_at().encode(env), self.issued_at.encode(env)).unwrap()
+            .map_put(device_os().encode(env), self.device_os.encode(env)).unwrap()
+            .map_put(device_model().encode(env), self.device_model.encode(env)).unwrap()
+            .map_put(language_code().encode(env), self.language_code.encode(env)).unwrap()
+            .map_put(game_version().encode(env), self.game_version.encode(env)).unwrap()
+            .map_put(platform_online_id().encode(env), self.platform_online_id.encode(env)).unwrap()
+            .map_put(authenticated().encode(env), self.authenticated.encode(env)).unwrap()
+    }
+}
+
+fn string_claim(claim: &Value) -> Option<String> {
+    claim.as_str().map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn reads_identity_and_device_claims() {
+        let chain = ValidatedChain {
+            identity_claims: json!({
+                "identityPublicKey": "key",
+                "iat": 1_700_000_000,
+                "extraData": {
+                    "XUID": "2535400000000000",
+                    "displayName": "Tim203",
+                    "identity": "00000000-0000-0000-0009-01f64f65c7c3",
+                    "titleId": "896928775",
+                },
+            }),
+            client_claims: json!({
+                "DeviceOS": 7,
+                "DeviceModel": "System Product Name",
+                "LanguageCode": "en_US",
+                "GameVersion": "1.20.40",
+                "PlatformOnlineId": "",
+                "SkinData": "ignored",
+            }),
+            authenticated: true,
+            valid_until: 1_700_003_600,
+        };
+
+        assert_eq!(LoginIdentity::from(&chain), LoginIdentity {
+            xuid: String::from("2535400000000000"),
+            display_name: String::from("Tim203"),
+            identity: String::from("00000000-0000-0000-0009-01f64f65c7c3"),
+            title_id: Some(String::from("896928775")),
+            sandbox_id: None,
+            identity_public_key: String::from("key"),
+            issued_at: Some(1_700_000_000),
+            device_os: Some(7),
+            device_model: Some(String::from("System Product Name")),
+            language_code: Some(String::from("en_US")),
+            game_version: Some(String::from("1.20.40")),
+            platform_online_id: None,
+            authenticated: true,
+        });
+    }
+}
@@ -1,15 +1,29 @@
+use rustler::{atoms, Encoder, Env, Term};
+use rustler::types::map::map_new;
 use serde_json::Value;
 
 use crate::skin_convert::converter::convert_skin as other_convert_skin;
+use crate::skin_convert::base_layer::{base_layer_fill, BaseLayerFill, fill_base_layer};
 use crate::skin_convert::ConvertResult::{Error, Invalid, Success};
-use crate::skin_convert::pixel_cleaner::clear_unused_pixels;
-use crate::skin_convert::skin_codec::{encode_image, ImageWithHashes};
+use crate::skin_convert::pixel_cleaner::{canonicalise_pixels, clear_unused_pixels};
+use crate::skin_convert::skin_codec::{CANONICAL_HASH_VERSION, encode_image, hash_version, ImageWithHashes};
 use crate::SkinModel;
 
+pub mod base_layer;
 pub mod converter;
 mod pixel_cleaner;
 pub mod chain_validator;
+pub mod key_source;
+pub mod login_identity;
+pub mod perceptual_hash;
+pub mod replay_guard;
 pub mod skin_codec;
+pub mod skin_validator;
+pub mod token_validator;
+#[cfg(test)]
+mod test_keys;
+#[cfg(test)]
+mod test_skins;
 
 #[derive(Debug)]
 pub enum ErrorType {
@@ -20,10 +34,31 @@ pub enum ErrorType {
 pub enum ConvertResult<'a> {
     Invalid(ErrorType),
     Error(&'a str),
-    Success(ImageWithHashes, bool),
+    Success(ImageWithHashes, bool, ConvertDiagnostics),
 }
 
-pub fn convert_skin(client_claims: &Value) -> ConvertResult {
+atoms! {
+    base_layer_fill_policy,
+    filled_base_pixels,
+}
+
+/// information about the changes made to the skin while converting it
+pub struct ConvertDiagnostics {
+    /// None when filling the base layer is disabled
+    pub base_layer_fill: Option<BaseLayerFill>,
+    /// the amount of base layer pixels that weren't opaque
+    pub filled_base_pixels: usize,
+}
+
+impl Encoder for ConvertDiagnostics {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        map_new(env)
+            .map_put(base_layer_fill_policy().encode(env), self.base_layer_fill.encode(env)).unwrap()
+            .map_put(filled_base_pixels().encode(env), self.filled_base_pixels.encode(env)).unwrap()
+    }
+}
+
+pub fn convert_skin(client_claims: &Value) -> ConvertResult<'_> {
     let collect_result = skin_codec::collect_skin_info(client_claims);
     if collect_result.is_err() {
         return Invalid(collect_result.err().unwrap());
@@ -55,8 +90,18 @@ pub fn convert_skin(client_claims: &Value) -> ConvertResult {
         is_classic = model == SkinModel::Classic;
     }
 
-    clear_unused_pixels(&mut raw_data, is_classic);
-    let data = encode_image(&mut raw_data);
+    let hash_version = hash_version();
+    clear_unused_pixels(&mut raw_data, is_classic, hash_version);
+    if hash_version == CANONICAL_HASH_VERSION {
+        canonicalise_pixels(&mut raw_data);
+    }
+
+    // Java renders the base layer as opaque, so it can be filled to look the same as on Bedrock.
+    // Filling changes the hashes, so it's disabled unless a fill has been configured
+    let base_layer_fill = base_layer_fill();
+    let filled_base_pixels = base_layer_fill.map_or(0, |fill| fill_base_layer(&mut raw_data, is_classic, fill));
+
+    let data = encode_image(&raw_data, hash_version);
 
-    Success(data, is_classic)
+    Success(data, is_classic, ConvertDiagnostics { base_layer_fill, filled_base_pixels })
 }
@@ -0,0 +1,144 @@
+use lazy_static::lazy_static;
+
+use crate::common::RGBA_CHANNELS;
+use crate::common::skin::SkinModel;
+use crate::common::texture::used_pixel_mask;
+use crate::skin_convert::skin_codec::{SKIN_HEIGHT, SKIN_WIDTH};
+
+/// the width and height of the blocks that the skin is divided in before comparing them
+const BLOCK_SIZE: usize = 4;
+const BLOCKS_PER_ROW: usize = SKIN_WIDTH / BLOCK_SIZE;
+const BLOCKS_PER_COLUMN: usize = SKIN_HEIGHT / BLOCK_SIZE;
+
+/// the luma and two colour differences (like YCbCr) of the blocks are hashed separately,
+/// so that recolouring a skin without changing its brightness changes the hash as well
+const CHANNELS: usize = 3;
+/// a horizontal and a vertical difference hash, one bit per pair of neighbouring blocks
+const CHANNEL_HASH_BITS: usize =
+    BLOCKS_PER_COLUMN * (BLOCKS_PER_ROW - 1) + BLOCKS_PER_ROW * (BLOCKS_PER_COLUMN - 1);
+
+pub const PERCEPTUAL_HASH_BITS: usize = CHANNELS * CHANNEL_HASH_BITS;
+pub const PERCEPTUAL_HASH_SIZE: usize = PERCEPTUAL_HASH_BITS.div_ceil(8);
+
+lazy_static! {
+    /// the pixels that are visible with either model, so that the hash doesn't depend on the model
+    static ref VISIBLE_PIXELS: Vec<bool> = used_pixel_mask(&SkinModel::Classic).iter()
+        .zip(used_pixel_mask(&SkinModel::Slim))
+        .map(|(&classic, &slim)| classic || slim)
+        .collect();
+}
+
+/// a difference hash (dHash) of every channel of a 64x64 skin that only looks at the visible pixels.
+/// Skins that look alike have hashes that only differ in a few bits, see similarity
+pub fn perceptual_hash(raw_data: &[u8]) -> Vec<u8> {
+    let mut bits = Vec::with_capacity(PERCEPTUAL_HASH_BITS);
+    for channel in block_channels(raw_data) {
+        let block = |x: usize, y: usize| channel[y * BLOCKS_PER_ROW + x];
+
+        let horizontal = (0..BLOCKS_PER_COLUMN)
+            .flat_map(|y| (0..BLOCKS_PER_ROW - 1).map(move |x| block(x, y) > block(x + 1, y)));
+        let vertical = (0..BLOCKS_PER_COLUMN - 1)
+            .flat_map(|y| (0..BLOCKS_PER_ROW).map(move |x| block(x, y) > block(x, y + 1)));
+        bits.extend(horizontal.chain(vertical));
+    }
+
+    let mut hash = vec![0u8; PERCEPTUAL_HASH_SIZE];
+    for (index, bit) in bits.into_iter().enumerate() {
+        if bit {
+            hash[index / 8] |= 1 << (index % 8);
+        }
+    }
+    hash
+}
+
+/// how alike the skins of the hashes are, from 0 (every bit differs) to 1 (identical).
+/// Returns None when one of them isn't a perceptual hash
+pub fn similarity(first: &[u8], second: &[u8]) -> Option<f64> {
+    if first.len() != PERCEPTUAL_HASH_SIZE || second.len() != PERCEPTUAL_HASH_SIZE {
+        return None;
+    }
+
+    let different_bits: u32 = first.iter().zip(second).map(|(first, second)| (first ^ second).count_ones()).sum();
+    Some(1.0 - different_bits as f64 / PERCEPTUAL_HASH_BITS as f64)
+}
+
+/// the average luma, blue difference and red difference of the visible pixels of every block.
+/// Invisible pixels are ignored and transparent pixels are as dark as black
+fn block_channels(raw_data: &[u8]) -> [Vec<i32>; CHANNELS] {
+    let mut totals = vec![([0i32; CHANNELS], 0i32); BLOCKS_PER_ROW * BLOCKS_PER_COLUMN];
+
+    let pixels = raw_data.chunks_exact(RGBA_CHANNELS).zip(VISIBLE_PIXELS.iter()).enumerate();
+    for (index, (pixel, &visible)) in pixels {
+        if !visible {
+            continue;
+        }
+        let (x, y) = (index % SKIN_WIDTH, index / SKIN_WIDTH);
+        let [red, green, blue, alpha] = [pixel[0], pixel[1], pixel[2], pixel[3]].map(i32::from);
+        // Rec. 601 luma, every channel is weighted by the alpha
+        let luma = (299 * red + 587 * green + 114 * blue) / 1000;
+
+        let total = &mut totals[(y / BLOCK_SIZE) * BLOCKS_PER_ROW + x / BLOCK_SIZE];
+        for (channel, value) in [luma, blue - luma, red - luma].into_iter().enumerate() {
+            total.0[channel] += value * alpha / 255;
+        }
+        total.1 += 1;
+    }
+
+    std::array::from_fn(|channel| {
+        totals.iter()
+            .map(|(colour, count)| colour[channel].checked_div(*count).unwrap_or(0))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::skin::{ALEX_SKIN, STEVE_SKIN};
+
+    use super::*;
+
+    #[test]
+    fn ignores_invisible_pixels() {
+        let mut skin = STEVE_SKIN.clone();
+        // the top left corner isn't used by either model
+        skin[..4 * RGBA_CHANNELS].fill(255);
+        assert_eq!(perceptual_hash(&skin), perceptual_hash(&STEVE_SKIN));
+    }
+
+    #[test]
+    fn similar_skins_have_similar_hashes() {
+        let mut skin = STEVE_SKIN.clone();
+        // a few pixels of the face
+        let face = (8 * SKIN_WIDTH + 8) * RGBA_CHANNELS;
+        skin[face..face + 3 * RGBA_CHANNELS].fill(200);
+
+        let steve = perceptual_hash(&STEVE_SKIN);
+        let similar = similarity(&steve, &perceptual_hash(&skin)).unwrap();
+        let different = similarity(&steve, &perceptual_hash(&ALEX_SKIN)).unwrap();
+
+        assert_eq!(similarity(&steve, &steve), Some(1.0));
+        assert!(similar > 0.95, "similarity was {}", similar);
+        assert!(different < 0.85, "similarity was {}", different);
+    }
+
+    #[test]
+    fn recolours_with_same_brightness_are_different() {
+        // Steve in shades of grey, every pixel keeps its luma
+        let mut grey = STEVE_SKIN.clone();
+        for pixel in grey.chunks_exact_mut(RGBA_CHANNELS) {
+            let luma = (299 * pixel[0] as u32 + 587 * pixel[1] as u32 + 114 * pixel[2] as u32) / 1000;
+            pixel[..3].fill(luma as u8);
+        }
+
+        // only the luma bits are the same
+        let similarity = similarity(&perceptual_hash(&STEVE_SKIN), &perceptual_hash(&grey)).unwrap();
+        assert!(similarity < 0.9, "similarity was {}", similarity);
+    }
+
+    #[test]
+    fn rejects_invalid_hashes() {
+        let hash = perceptual_hash(&STEVE_SKIN);
+        assert_eq!(similarity(&hash, &hash[1..]), None);
+        assert_eq!(similarity(&[], &[]), None);
+    }
+}
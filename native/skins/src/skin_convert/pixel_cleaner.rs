@@ -1,150 +1,114 @@
-use crate::common::RGBA_CHANNELS;
+use crate::common::{OffsetAndDimension, RGBA_CHANNELS};
+use crate::common::skin::{PARTS, SkinLayer, SkinModel, SkinSection};
+use crate::common::texture::{texture_position, used_pixel_mask};
+use crate::skin_convert::skin_codec::{RAW_HASH_VERSION, SKIN_WIDTH};
 
-pub fn clear_unused_pixels(raw_data: &mut [u8], is_steve: bool) -> &mut [u8] {
-    // clear the unused sections of a 64x64 skin
+/// overlay pixels with a lower alpha aren't rendered by Minecraft, its entity shaders discard an alpha below 0.1
+const OVERLAY_ALPHA_CUTOFF: u8 = 26;
 
-    // first row
-    for x in 0..8 {
-        for y in 0..8 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-        }
-    }
-    for x in 24..40 {
-        for y in 0..8 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-        }
-    }
-    for x in 56..64 {
-        for y in 0..8 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-        }
-    }
-    // second row
-    for x in 0..4 {
-        for y in 16..20 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-        }
-    }
-    for x in 12..20 {
-        for y in 16..20 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-        }
-    }
-    for x in 36..44 {
-        for y in 16..20 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-        }
-    }
-    // third row
-    for x in 0..4 {
-        for y in 32..36 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
+/// unused pixels of slim skins that the raw hash version doesn't clear,
+/// the stored skins have been converted by a cleaner that missed them
+const SLIM_RAW_KEPT_PIXELS: OffsetAndDimension = OffsetAndDimension { x_offset: 52, y_offset: 32, width: 2, height: 4 };
+
+/// clears every pixel of a 64x64 skin that isn't used by any face of the model.
+/// The raw hash version keeps the pixels that the original cleaner kept, so that its hashes don't change
+pub fn clear_unused_pixels(raw_data: &mut [u8], is_steve: bool, hash_version: u32) -> &mut [u8] {
+    let model = if is_steve { SkinModel::Classic } else { SkinModel::Slim };
+    let keep = |index: usize| {
+        let (x, y) = (index % SKIN_WIDTH, index / SKIN_WIDTH);
+        let kept = &SLIM_RAW_KEPT_PIXELS;
+        !is_steve && hash_version == RAW_HASH_VERSION
+            && (kept.x_offset..kept.x_offset + kept.width).contains(&x)
+            && (kept.y_offset..kept.y_offset + kept.height).contains(&y)
+    };
+
+    let pixels = raw_data.chunks_exact_mut(RGBA_CHANNELS).zip(used_pixel_mask(&model)).enumerate();
+    for (index, (pixel, used)) in pixels {
+        if !used && !keep(index) {
+            pixel.fill(0);
         }
     }
-    for x in 12..20 {
-        for y in 32..36 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
+    raw_data
+}
+
+/// makes skins that look the same in-game have the same pixels, so that they also have the same hashes.
+/// Overlay pixels that Minecraft doesn't render become transparent and transparent pixels become (0, 0, 0, 0)
+pub fn canonicalise_pixels(raw_data: &mut [u8]) -> &mut [u8] {
+    for part in PARTS {
+        let Some(overlay) = texture_position(SkinSection(&part, SkinLayer::Top)) else { continue };
+
+        for y in overlay.y_offset..overlay.y_offset + overlay.height {
+            let row = (y * SKIN_WIDTH + overlay.x_offset) * RGBA_CHANNELS;
+            for pixel in raw_data[row..row + overlay.width * RGBA_CHANNELS].chunks_exact_mut(RGBA_CHANNELS) {
+                if pixel[3] < OVERLAY_ALPHA_CUTOFF {
+                    pixel[3] = 0;
+                }
+            }
         }
     }
-    for x in 36..44 {
-        for y in 32..36 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
+
+    for pixel in raw_data.chunks_exact_mut(RGBA_CHANNELS) {
+        if pixel[3] == 0 {
+            pixel.fill(0);
         }
     }
-    // fourth row
-    for x in 0..4 {
-        for y in 48..52 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-        }
+    raw_data
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use crate::skin_convert::skin_codec::{CANONICAL_HASH_VERSION, SKIN_HEIGHT};
+
+    use super::*;
+
+    /// the areas (x and y range) that the original cleaner cleared for both models
+    const CLEARED: [(Range<usize>, Range<usize>); 12] = [
+        (0..8, 0..8), (24..40, 0..8), (56..64, 0..8),
+        (0..4, 16..20), (12..20, 16..20), (36..44, 16..20),
+        (0..4, 32..36), (12..20, 32..36), (36..44, 32..36),
+        (0..4, 48..52), (12..20, 48..52), (28..36, 48..52),
+    ];
+    const CLEARED_CLASSIC: [(Range<usize>, Range<usize>); 5] = [
+        (52..56, 16..20), (52..56, 32..36), (44..52, 48..52), (60..64, 48..52), (56..64, 16..48),
+    ];
+    const CLEARED_SLIM: [(Range<usize>, Range<usize>); 7] = [
+        (50..54, 16..20), (50..52, 32..36), (42..52, 48..52), (46..48, 52..64),
+        (58..64, 48..52), (62..64, 52..64), (54..64, 16..48),
+    ];
+
+    /// which pixels are cleared when every pixel is opaque
+    fn cleared_pixels(is_steve: bool, hash_version: u32) -> Vec<bool> {
+        let mut skin = vec![255; SKIN_WIDTH * SKIN_HEIGHT * RGBA_CHANNELS];
+        clear_unused_pixels(&mut skin, is_steve, hash_version);
+        skin.chunks_exact(RGBA_CHANNELS).map(|pixel| pixel[3] == 0).collect()
     }
-    for x in 12..20 {
-        for y in 48..52 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
+
+    fn original_cleared_pixels(is_steve: bool) -> Vec<bool> {
+        let model_areas: &[_] = if is_steve { &CLEARED_CLASSIC } else { &CLEARED_SLIM };
+        let mut cleared = vec![false; SKIN_WIDTH * SKIN_HEIGHT];
+        for (x_range, y_range) in CLEARED.iter().chain(model_areas) {
+            for y in y_range.clone() {
+                for x in x_range.clone() {
+                    cleared[y * SKIN_WIDTH + x] = true;
+                }
+            }
         }
+        cleared
     }
-    for x in 28..36 {
-        for y in 48..52 {
-            set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-        }
+
+    #[test]
+    fn raw_version_clears_like_the_original_cleaner() {
+        assert_eq!(cleared_pixels(true, RAW_HASH_VERSION), original_cleared_pixels(true));
+        assert_eq!(cleared_pixels(false, RAW_HASH_VERSION), original_cleared_pixels(false));
     }
-    // Alex skins have more empty space then Steve skins
-    if is_steve {
-        // second row
-        for x in 52..56 {
-            for y in 16..20 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        // third row
-        for x in 52..56 {
-            for y in 32..36 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        // fourth row
-        for x in 44..52 {
-            for y in 48..52 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        for x in 60..64 {
-            for y in 48..52 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        // big unused area in row 2 and 3
-        for x in 56..64 {
-            for y in 16..48 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-    } else {
-        // second row
-        for x in 50..54 {
-            for y in 16..20 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        // third row
-        for x in 50..52 {
-            for y in 32..36 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        // fourth row
-        for x in 42..52 {
-            for y in 48..52 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        for x in 46..48 {
-            for y in 52..64 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        for x in 58..64 {
-            for y in 48..52 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        for x in 62..64 {
-            for y in 52..64 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
-        }
-        // big unused area in row 2 and 3
-        for x in 54..64 {
-            for y in 16..48 {
-                set_pixel(raw_data, x, y, 64, 0, 0, 0, 0);
-            }
+
+    #[test]
+    fn canonical_version_clears_every_unused_pixel() {
+        for (is_steve, model) in [(true, SkinModel::Classic), (false, SkinModel::Slim)] {
+            let unused: Vec<bool> = used_pixel_mask(&model).iter().map(|used| !used).collect();
+            assert_eq!(cleared_pixels(is_steve, CANONICAL_HASH_VERSION), unused);
         }
     }
-    raw_data
 }
-
-fn set_pixel(vec: &mut [u8], x: usize, y: usize, width: usize, r: u8, g: u8, b: u8, a: u8) {
-    vec[(y * width + x) * RGBA_CHANNELS] = r;
-    vec[(y * width + x) * RGBA_CHANNELS + 1] = g;
-    vec[(y * width + x) * RGBA_CHANNELS + 2] = b;
-    vec[(y * width + x) * RGBA_CHANNELS + 3] = a;
-}
\ No newline at end of file
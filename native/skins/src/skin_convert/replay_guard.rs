@@ -0,0 +1,135 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use jsonwebtokens::raw::{split_token, TokenSlices};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+
+use crate::skin_convert::chain_validator::ChainError;
+
+/// the default max amount of signatures that are remembered, which takes about 10MB
+pub const DEFAULT_REPLAY_CAPACITY: usize = 100_000;
+
+lazy_static! {
+    static ref REPLAY_GUARD: Mutex<ReplayGuard> = Mutex::new(ReplayGuard::new(DEFAULT_REPLAY_CAPACITY));
+}
+
+/// replaces the guard used by check_replay, which forgets every signature that has been seen.
+/// A capacity of 0 disables the replay protection
+pub fn configure(capacity: usize) {
+    *REPLAY_GUARD.lock().unwrap() = ReplayGuard::new(capacity);
+}
+
+/// rejects client data that has already been submitted while its login chain is still valid.
+/// Every login signs its own client data, so a duplicate can only be a captured (and resubmitted) login
+pub fn check_replay(client_data: &str, valid_until: u64, now: u64) -> Result<(), ChainError> {
+    let TokenSlices { signature, .. } = split_token(client_data).map_err(|_| ChainError::InvalidClientData)?;
+    let signature = base64::decode_config(signature, base64::URL_SAFE_NO_PAD)
+        .map_err(|_| ChainError::InvalidClientData)?;
+
+    let hash = Sha256::digest(signature_r(&signature)?).into();
+    if REPLAY_GUARD.lock().unwrap().register(hash, valid_until, now) {
+        Ok(())
+    } else {
+        Err(ChainError::Replayed)
+    }
+}
+
+/// the r of an ES384 (r || s) signature. (r, s) and (r, n - s) are both valid signatures of the same
+/// client data, so only r identifies the signature
+fn signature_r(signature: &[u8]) -> Result<&[u8], ChainError> {
+    if signature.is_empty() || !signature.len().is_multiple_of(2) {
+        return Err(ChainError::InvalidClientData);
+    }
+    Ok(&signature[..signature.len() / 2])
+}
+
+/// a bounded set of the signature hashes of logins that are still valid.
+/// When the set is full the hash that expires first is forgotten, even when it didn't expire yet
+pub struct ReplayGuard {
+    capacity: usize,
+    /// the hashes and the time they expire
+    seen: HashMap<[u8; 32], u64>,
+    /// the same hashes ordered by the time they expire, soonest first
+    expiries: BTreeSet<(u64, [u8; 32])>,
+}
+
+impl ReplayGuard {
+    pub fn new(capacity: usize) -> ReplayGuard {
+        ReplayGuard { capacity, seen: HashMap::new(), expiries: BTreeSet::new() }
+    }
+
+    /// remembers the hash until it expires, returns false when the hash has been seen and didn't expire yet
+    pub fn register(&mut self, hash: [u8; 32], expires_at: u64, now: u64) -> bool {
+        if self.capacity == 0 {
+            return true;
+        }
+        if self.seen.get(&hash).is_some_and(|&seen_until| now <= seen_until) {
+            return false;
+        }
+
+        while let Some(&(seen_until, oldest)) = self.expiries.first() {
+            if now <= seen_until && self.seen.len() < self.capacity {
+                break;
+            }
+            self.expiries.pop_first();
+            self.seen.remove(&oldest);
+        }
+
+        if now <= expires_at {
+            self.seen.insert(hash, expires_at);
+            self.expiries.insert((expires_at, hash));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_duplicates_until_expired() {
+        let mut guard = ReplayGuard::new(10);
+        assert!(guard.register([1; 32], 1060, 1000));
+        assert!(guard.register([2; 32], 2000, 1000));
+        assert!(!guard.register([1; 32], 1060, 1060));
+
+        // the login of the first hash expired
+        assert!(guard.register([1; 32], 1060, 1061));
+        assert!(!guard.register([2; 32], 2000, 1061));
+    }
+
+    #[test]
+    fn forgets_first_expiring_when_full() {
+        let mut guard = ReplayGuard::new(2);
+        assert!(guard.register([1; 32], 1500, 1000));
+        assert!(guard.register([2; 32], 1100, 1001));
+        assert!(guard.register([3; 32], 1200, 1002));
+
+        assert!(!guard.register([1; 32], 1500, 1003));
+        assert!(!guard.register([3; 32], 1200, 1003));
+        assert!(guard.register([2; 32], 1100, 1003));
+    }
+
+    #[test]
+    fn keys_signatures_on_r() {
+        let r = [7; 48];
+        let mut signature = [r, [1; 48]].concat();
+        let mut malleated = [r, [2; 48]].concat();
+        assert_eq!(signature_r(&signature), signature_r(&malleated));
+
+        signature[0] = 8;
+        assert_ne!(signature_r(&signature), signature_r(&malleated));
+        malleated.pop();
+        assert_eq!(signature_r(&malleated), Err(ChainError::InvalidClientData));
+        assert_eq!(signature_r(&[]), Err(ChainError::InvalidClientData));
+    }
+
+    #[test]
+    fn disabled_without_capacity() {
+        let mut guard = ReplayGuard::new(0);
+        assert!(guard.register([1; 32], 2000, 1000));
+        assert!(guard.register([1; 32], 2000, 1000));
+    }
+}
@@ -1,18 +1,44 @@
 use std::str::from_utf8;
+use std::sync::atomic::{AtomicU32, Ordering};
 use base64::decode;
 use json::{JsonValue, parse};
-use lodepng::FilterStrategy;
 use serde_json::Value;
 use sha2::{Digest, Sha256};
+use crate::common::encoder::encode_minecraft_png;
 use crate::common::RGBA_CHANNELS;
 use crate::skin_convert::ErrorType;
+use crate::skin_convert::perceptual_hash::perceptual_hash;
 
 use crate::skin_convert::ErrorType::{InvalidGeometry, InvalidSize};
 
-pub const SKIN_WIDTH: usize = 64;
-pub const SKIN_HEIGHT: usize = 64;
+pub use crate::common::{SKIN_HEIGHT, SKIN_WIDTH};
 pub const SKIN_CHANNELS: usize = RGBA_CHANNELS;
 
+// the versions of the hashes, a new version has to be added whenever the hashed data or the png encoding changes.
+// Stored hashes of another version have to be recomputed from the skin before they can be compared
+
+/// hash is the sha256 of the raw RGBA, minecraft_hash is the sha256 of the png made by encode_minecraft_png
+pub const RAW_HASH_VERSION: u32 = 1;
+/// like RAW_HASH_VERSION, but the skin is canonicalised first so that invisible differences don't change the hashes
+pub const CANONICAL_HASH_VERSION: u32 = 2;
+
+/// the hashes of the existing skins are made with the raw version,
+/// the canonical version can be enabled once they have been migrated
+static HASH_VERSION: AtomicU32 = AtomicU32::new(RAW_HASH_VERSION);
+
+/// changes the hash version of converted skins, returns false when the version isn't supported
+pub fn set_hash_version(version: u32) -> bool {
+    if version != RAW_HASH_VERSION && version != CANONICAL_HASH_VERSION {
+        return false;
+    }
+    HASH_VERSION.store(version, Ordering::Relaxed);
+    true
+}
+
+pub fn hash_version() -> u32 {
+    HASH_VERSION.load(Ordering::Relaxed)
+}
+
 pub struct SkinInfo {
     pub needs_convert: bool,
     pub raw_skin_data: Vec<u8>,
@@ -26,6 +52,10 @@ pub struct ImageWithHashes {
     pub png: Box<[u8]>,
     pub minecraft_hash: Box<[u8]>,
     pub hash: Box<[u8]>,
+    /// the hash version that the hashes were made with
+    pub hash_version: u32,
+    /// only present for 64x64 images, see perceptual_hash
+    pub perceptual_hash: Option<Box<[u8]>>,
 }
 
 pub fn collect_skin_info(client_claims: &Value) -> Result<SkinInfo, ErrorType> {
@@ -93,21 +123,13 @@ pub fn collect_skin_info(client_claims: &Value) -> Result<SkinInfo, ErrorType> {
     })
 }
 
-pub fn encode_image(raw_data: &mut [u8]) -> ImageWithHashes {
-    encode_custom_image(raw_data, SKIN_WIDTH, SKIN_HEIGHT)
+/// encodes a 64x64 skin, the caller has to have canonicalised the skin for the canonical hash version
+pub fn encode_image(raw_data: &[u8], hash_version: u32) -> ImageWithHashes {
+    ImageWithHashes { hash_version, ..encode_custom_image(raw_data, SKIN_WIDTH, SKIN_HEIGHT) }
 }
 
 pub fn encode_custom_image(raw_data: &[u8], width: usize, height: usize) -> ImageWithHashes {
-    // encode images like Minecraft does
-    let mut encoder = lodepng::Encoder::new();
-    encoder.set_auto_convert(false);
-    encoder.info_png_mut().interlace_method = 0; // should be 0 but just to be sure
-
-    let mut encoder_settings = encoder.settings_mut();
-    encoder_settings.zlibsettings.set_level(4);
-    encoder_settings.filter_strategy = FilterStrategy::ZERO;
-
-    let png = encoder.encode(raw_data, width, height).unwrap();
+    let png = encode_minecraft_png(raw_data, width, height);
 
     let mut hasher = Sha256::new();
 
@@ -122,5 +144,142 @@ pub fn encode_custom_image(raw_data: &[u8], width: usize, height: usize) -> Imag
         png: Box::from(png.as_slice()),
         minecraft_hash: Box::from(minecraft_hash.as_slice()),
         hash: Box::from(hash.as_slice()),
+        hash_version: RAW_HASH_VERSION,
+        perceptual_hash: (width == SKIN_WIDTH && height == SKIN_HEIGHT)
+            .then(|| perceptual_hash(raw_data).into_boxed_slice()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::json;
+
+    use crate::common::skin::{ALEX_SKIN, STEVE_SKIN};
+    use crate::skin_convert::{convert_skin, ConvertResult};
+    use crate::skin_convert::pixel_cleaner::canonicalise_pixels;
+
+    use super::*;
+
+    /// the files in this directory lock the exact png bytes (and so the Minecraft hash) of every hash version.
+    /// Run the tests with UPDATE_GOLDEN_FILES=1 to write the encoded ones, but only after adding a new hash version
+    const GOLDEN_DIR: &str = "resources/golden";
+
+    fn hex(data: &[u8]) -> String {
+        data.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// a skin with every kind of pixel: opaque, semi-transparent and transparent with leftover RGB
+    fn pattern_skin() -> Vec<u8> {
+        (0..SKIN_HEIGHT).flat_map(|y| (0..SKIN_WIDTH).flat_map(move |x| {
+            let alpha = match (x + y) % 4 {
+                0 => 0,
+                1 => 128,
+                _ => 255,
+            };
+            [(x * 4) as u8, (y * 4) as u8, ((x ^ y) * 4) as u8, alpha]
+        })).collect()
+    }
+
+    fn assert_golden(name: &str, raw_data: &[u8], hash_version: u32, expected_hash: &str) {
+        let image = encode_image(raw_data, hash_version);
+        if std::env::var_os("UPDATE_GOLDEN_FILES").is_some() {
+            fs::write(format!("{}/{}.png", GOLDEN_DIR, name), &image.png).unwrap();
+        }
+        assert_golden_image(name, &image, hash_version, expected_hash);
     }
-}
\ No newline at end of file
+
+    fn assert_golden_image(name: &str, image: &ImageWithHashes, hash_version: u32, expected_hash: &str) {
+        let golden = fs::read(format!("{}/{}.png", GOLDEN_DIR, name)).unwrap();
+        assert!(
+            *image.png == *golden,
+            "the png of {} changed, which changes its Minecraft hash. Add a new hash version when this is intended",
+            name
+        );
+        assert_eq!(hex(&image.minecraft_hash), hex(&Sha256::digest(&golden)));
+        assert_eq!(hex(&image.hash), expected_hash, "the hash of {} changed, add a new hash version", name);
+        assert_eq!(image.hash_version, hash_version);
+    }
+
+    #[test]
+    fn steve_matches_golden_file() {
+        assert_golden("steve_classic", &STEVE_SKIN, RAW_HASH_VERSION, "c1c3474985ecd2c523ca53e9126a365348b39c7d6a3a03adf896041c8d1c3c69");
+    }
+
+    #[test]
+    fn alex_matches_golden_file() {
+        assert_golden("alex_slim", &ALEX_SKIN, RAW_HASH_VERSION, "a624fd2823a53aeef2f5be22795373e4ebc47767b873dd3f30c7ba708ffd5859");
+    }
+
+    #[test]
+    fn pattern_matches_golden_file() {
+        assert_golden("pattern", &pattern_skin(), RAW_HASH_VERSION, "4e12b6d3ad6f1a5a880acc4f212da4025bf7bdeca32f3140b615e17a043253ab");
+    }
+
+    /// converts the skin like a Bedrock client would send it, with the hash version that is configured by default
+    fn convert(raw_data: &[u8], is_steve: bool) -> ImageWithHashes {
+        let geometry = if is_steve { "geometry.humanoid.custom" } else { "geometry.humanoid.customSlim" };
+        let claims = json!({
+            "SkinImageWidth": SKIN_WIDTH,
+            "SkinImageHeight": SKIN_HEIGHT,
+            "SkinData": base64::encode(raw_data),
+            "SkinResourcePatch": base64::encode(format!(r#"{{"geometry":{{"default":"{}"}}}}"#, geometry)),
+            "SkinGeometryData": "bnVsbAo=",
+            "ArmSize": if is_steve { "wide" } else { "slim" },
+        });
+
+        let ConvertResult::Success(image, converted_is_steve, _) = convert_skin(&claims) else {
+            panic!("failed to convert the skin");
+        };
+        assert_eq!(converted_is_steve, is_steve);
+        image
+    }
+
+    // the converted golden files have been made by the converter that made the stored unique skins,
+    // so they should never be updated
+
+    #[test]
+    fn converted_classic_pattern_matches_golden_file() {
+        let image = convert(&pattern_skin(), true);
+        assert_golden_image(
+            "pattern_converted_classic", &image, RAW_HASH_VERSION,
+            "1dfaff3c2376757d1c7ce8ab4e9b7e64d96602db0800d2301b67229bf4ab09f2"
+        );
+    }
+
+    #[test]
+    fn converted_slim_pattern_matches_golden_file() {
+        let image = convert(&pattern_skin(), false);
+        assert_golden_image(
+            "pattern_converted_slim", &image, RAW_HASH_VERSION,
+            "40c59e0761d2d69e2c8accad17d9dfdb05811b89f9493ca1562f6f4ee525eaa1"
+        );
+    }
+
+    #[test]
+    fn canonical_pattern_matches_golden_file() {
+        let mut skin = pattern_skin();
+        canonicalise_pixels(&mut skin);
+        assert_golden("pattern_canonical", &skin, CANONICAL_HASH_VERSION, "4146f080ce7967be1cd89a386a40096542c334380756929b08ab073448362c1c");
+    }
+
+    #[test]
+    fn canonical_hash_ignores_invisible_pixels() {
+        let mut skin = pattern_skin();
+        // only differs in the RGB of transparent pixels and in overlay pixels that Minecraft doesn't render
+        let mut other = skin.clone();
+        for pixel in other.chunks_exact_mut(SKIN_CHANNELS) {
+            if pixel[3] == 0 {
+                pixel[..3].fill(7);
+            }
+        }
+        let overlay_pixel = (32 * SKIN_WIDTH + 20) * SKIN_CHANNELS;
+        skin[overlay_pixel + 3] = 0;
+        other[overlay_pixel + 3] = 25;
+
+        canonicalise_pixels(&mut skin);
+        canonicalise_pixels(&mut other);
+        assert_eq!(encode_image(&skin, CANONICAL_HASH_VERSION).hash, encode_image(&other, CANONICAL_HASH_VERSION).hash);
+    }
+}
@@ -0,0 +1,160 @@
+use rustler::{atoms, Encoder, Env, Term};
+use rustler::types::tuple::make_tuple;
+
+use crate::common::encoder::encode_minecraft_png;
+use crate::common::skin::{PARTS, SkinModel, SkinPart};
+use crate::common::texture_decoder::decode_png;
+use crate::skin_convert::base_layer::{base_layer_faces, face_coordinates, make_base_layer_opaque, pixel_index};
+
+/// the largest skin file that Mojang accepts, in bytes
+pub const MAX_FILE_SIZE: usize = 24 * 1024;
+
+atoms! {
+    invalid_image,
+    invalid_dimensions,
+    file_too_large,
+    transparent_base_layer,
+}
+
+/// a reason why Mojang wouldn't accept the skin
+pub enum Violation {
+    InvalidImage,
+    /// only 64x64 and (legacy) 64x32 skins are accepted
+    InvalidDimensions(usize, usize),
+    FileTooLarge(usize),
+    /// the part and the amount of pixels in its base layer that aren't fully opaque
+    TransparentBaseLayer(SkinPart, usize),
+}
+
+impl Encoder for Violation {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            Violation::InvalidImage => invalid_image().encode(env),
+            Violation::InvalidDimensions(width, height) =>
+                make_tuple(env, &[invalid_dimensions().encode(env), width.encode(env), height.encode(env)]),
+            Violation::FileTooLarge(size) => make_tuple(env, &[file_too_large().encode(env), size.encode(env)]),
+            Violation::TransparentBaseLayer(part, pixel_count) =>
+                make_tuple(env, &[transparent_base_layer().encode(env), part.encode(env), pixel_count.encode(env)]),
+        }
+    }
+}
+
+pub struct ValidationResult {
+    /// the violations of the fixed skin when it has been fixed, otherwise the violations of the given skin
+    pub violations: Vec<Violation>,
+    /// the violations of the given skin that have been fixed, empty when the skin hasn't been fixed
+    pub fixed_violations: Vec<Violation>,
+    /// the fixed png, only present when auto fix is enabled and the skin had something to fix
+    pub fixed: Option<Vec<u8>>,
+}
+
+/// checks whether Mojang would accept the given skin png.
+/// Auto fix makes the base layer opaque, which is the only violation that can be fixed
+pub fn validate_java_skin(png: &[u8], model: &SkinModel, auto_fix: bool) -> ValidationResult {
+    let violations = find_violations(png, model);
+
+    let is_fixable = |violation: &Violation| matches!(violation, Violation::TransparentBaseLayer(..));
+    if !auto_fix || !violations.iter().any(is_fixable) {
+        return ValidationResult { violations, fixed_violations: Vec::new(), fixed: None };
+    }
+    let fixed_violations = violations.into_iter().filter(is_fixable).collect();
+
+    // the skin could be decoded and has valid dimensions, otherwise the base layer wouldn't have been checked
+    let mut image = decode_png(png).unwrap();
+    make_base_layer_opaque(&mut image.data, image.height, model);
+
+    let fixed = encode_minecraft_png(&image.data, image.width, image.height);
+    ValidationResult { violations: find_violations(&fixed, model), fixed_violations, fixed: Some(fixed) }
+}
+
+fn find_violations(png: &[u8], model: &SkinModel) -> Vec<Violation> {
+    let mut violations = Vec::new();
+
+    if png.len() > MAX_FILE_SIZE {
+        violations.push(Violation::FileTooLarge(png.len()));
+    }
+
+    let Ok(image) = decode_png(png) else {
+        violations.push(Violation::InvalidImage);
+        return violations;
+    };
+
+    if image.width != 64 || (image.height != 64 && image.height != 32) {
+        violations.push(Violation::InvalidDimensions(image.width, image.height));
+        return violations;
+    }
+
+    for part in PARTS {
+        let pixel_count = base_layer_faces(&part, image.height, model)
+            .flat_map(|face| face_coordinates(&face))
+            .filter(|&(x, y)| image.data[pixel_index(x, y) + 3] != 255)
+            .count();
+
+        if pixel_count > 0 {
+            violations.push(Violation::TransparentBaseLayer(part, pixel_count));
+        }
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::RGBA_CHANNELS;
+    use crate::common::skin::STEVE_SKIN;
+    use crate::skin_convert::test_skins::{head_front, transparent_face};
+
+    use super::*;
+
+    /// Steve with a transparent front of the head
+    fn transparent_face_png() -> Vec<u8> {
+        encode_minecraft_png(&transparent_face(), 64, 64)
+    }
+
+    #[test]
+    fn accepts_default_skin() {
+        let png = encode_minecraft_png(&STEVE_SKIN, 64, 64);
+        let result = validate_java_skin(&png, &SkinModel::Classic, true);
+        assert!(result.violations.is_empty());
+        assert!(result.fixed_violations.is_empty());
+        assert!(result.fixed.is_none());
+
+        let legacy = encode_minecraft_png(&STEVE_SKIN[..64 * 32 * RGBA_CHANNELS], 64, 32);
+        assert!(validate_java_skin(&legacy, &SkinModel::Classic, false).violations.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_images() {
+        let result = validate_java_skin(b"not a png", &SkinModel::Classic, true);
+        assert!(matches!(result.violations[..], [Violation::InvalidImage]));
+        assert!(result.fixed.is_none());
+
+        let too_large = vec![0; MAX_FILE_SIZE + 1];
+        let result = validate_java_skin(&too_large, &SkinModel::Classic, false);
+        assert!(matches!(result.violations[..], [Violation::FileTooLarge(size), Violation::InvalidImage] if size == MAX_FILE_SIZE + 1));
+    }
+
+    #[test]
+    fn rejects_invalid_dimensions() {
+        let png = encode_minecraft_png(&vec![255; 32 * 32 * RGBA_CHANNELS], 32, 32);
+        let result = validate_java_skin(&png, &SkinModel::Classic, true);
+        assert!(matches!(result.violations[..], [Violation::InvalidDimensions(32, 32)]));
+        assert!(result.fixed.is_none());
+    }
+
+    #[test]
+    fn reports_transparent_base_layer() {
+        let result = validate_java_skin(&transparent_face_png(), &SkinModel::Classic, false);
+        assert!(matches!(result.violations[..], [Violation::TransparentBaseLayer(SkinPart::Head, 64)]));
+        assert!(result.fixed.is_none());
+    }
+
+    #[test]
+    fn fixes_transparent_base_layer() {
+        let result = validate_java_skin(&transparent_face_png(), &SkinModel::Classic, true);
+        assert!(result.violations.is_empty());
+        assert!(matches!(result.fixed_violations[..], [Violation::TransparentBaseLayer(SkinPart::Head, 64)]));
+
+        let fixed = decode_png(&result.fixed.unwrap()).unwrap();
+        assert!(face_coordinates(&head_front()).all(|(x, y)| fixed.data[pixel_index(x, y) + 3] == 255));
+    }
+}
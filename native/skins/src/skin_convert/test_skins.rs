@@ -0,0 +1,19 @@
+//! skins with known problems that the converter and validator tests share
+
+use crate::common::OffsetAndDimension;
+use crate::common::skin::{SkinFace, SkinLayer, SkinModel, SkinPart, STEVE_SKIN};
+use crate::common::texture::texture_position_face;
+use crate::skin_convert::base_layer::{face_coordinates, pixel_index};
+
+pub fn head_front() -> OffsetAndDimension {
+    texture_position_face(&SkinPart::Head, &SkinLayer::Bottom, &SkinFace::Front, &SkinModel::Classic).unwrap()
+}
+
+/// Steve with a transparent front of the head
+pub fn transparent_face() -> Vec<u8> {
+    let mut skin = STEVE_SKIN.clone();
+    for (x, y) in face_coordinates(&head_front()) {
+        skin[pixel_index(x, y) + 3] = 0;
+    }
+    skin
+}
@@ -0,0 +1,282 @@
+use std::sync::RwLock;
+
+use jsonwebtokens::raw::{decode_json_token_slice, split_token, TokenSlices, verify_signature_only};
+use lazy_static::lazy_static;
+use rustler::{atoms, Encoder, Env, Term};
+use serde_json::Value;
+
+use crate::skin_convert::chain_validator::{check_time, TimeError};
+use crate::skin_convert::key_source::{DEFAULT_ACTIVE_KEY_SETS, KeySource, RotatingKeys};
+
+/// the claims of the multiplayer token that the newer Bedrock versions log in with
+pub const XUID_CLAIM: &str = "xid";
+pub const GAMERTAG_CLAIM: &str = "xname";
+/// the base64 encoded DER public key that signs the client data, like the identityPublicKey of a login chain
+pub const CLIENT_KEY_CLAIM: &str = "cpk";
+
+lazy_static! {
+    /// the keys that the NIFs validate tokens with, Elixir rotates in the keys of the discovery endpoint
+    pub static ref TOKEN_KEYS: RwLock<RotatingKeys> = RwLock::new(RotatingKeys::new(DEFAULT_ACTIVE_KEY_SETS));
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenError {
+    /// the token isn't a valid jwt or has a time claim that isn't a valid unix time
+    MalformedToken,
+    /// none of the keys can have signed the token
+    UnknownKey,
+    InvalidSignature,
+    Expired,
+    NotYetValid,
+    InvalidIssuer,
+    InvalidAudience,
+}
+
+atoms! {
+    malformed_token,
+    unknown_key,
+    invalid_signature,
+    expired,
+    not_yet_valid,
+    invalid_issuer,
+    invalid_audience,
+}
+
+impl Encoder for TokenError {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            TokenError::MalformedToken => malformed_token().encode(env),
+            TokenError::UnknownKey => unknown_key().encode(env),
+            TokenError::InvalidSignature => invalid_signature().encode(env),
+            TokenError::Expired => expired().encode(env),
+            TokenError::NotYetValid => not_yet_valid().encode(env),
+            TokenError::InvalidIssuer => invalid_issuer().encode(env),
+            TokenError::InvalidAudience => invalid_audience().encode(env),
+        }
+    }
+}
+
+impl From<TimeError> for TokenError {
+    fn from(err: TimeError) -> Self {
+        match err {
+            TimeError::Expired => TokenError::Expired,
+            TimeError::NotYetValid => TokenError::NotYetValid,
+            TimeError::Malformed => TokenError::MalformedToken,
+        }
+    }
+}
+
+pub struct TokenOptions {
+    /// the iss claim has to match the issuer
+    pub issuer: String,
+    /// the aud claim has to be or contain the audience, when present
+    pub audience: Option<String>,
+    /// the amount of seconds that exp and nbf may be off
+    pub clock_skew: u64,
+    /// the current unix time in seconds
+    pub now: u64,
+}
+
+/// validates an OpenID Connect (id) token, like the tokens that the newer Bedrock versions log in with.
+/// Returns the claims of the token
+pub fn validate_token(token: &str, keys: &dyn KeySource, options: &TokenOptions) -> Result<Value, TokenError> {
+    let TokenSlices { message, signature, header, claims } =
+        split_token(token).map_err(|_| TokenError::MalformedToken)?;
+
+    let header = decode_json_token_slice(header).map_err(|_| TokenError::MalformedToken)?;
+    let claims = decode_json_token_slice(claims).map_err(|_| TokenError::MalformedToken)?;
+    if !claims.is_object() {
+        return Err(TokenError::MalformedToken);
+    }
+
+    let key_id = match &header["kid"] {
+        Value::Null => None,
+        Value::String(key_id) => Some(key_id.as_str()),
+        _ => return Err(TokenError::MalformedToken),
+    };
+
+    let keys = keys.keys(key_id);
+    if keys.is_empty() {
+        return Err(TokenError::UnknownKey);
+    }
+    if !keys.iter().any(|key| verify_signature_only(&header, message, signature, key).is_ok()) {
+        return Err(TokenError::InvalidSignature);
+    }
+
+    if claims["exp"].is_null() {
+        return Err(TokenError::MalformedToken);
+    }
+    check_time(&claims, options.clock_skew, options.now)?;
+
+    if claims["iss"].as_str() != Some(options.issuer.as_str()) {
+        return Err(TokenError::InvalidIssuer);
+    }
+
+    if let Some(audience) = &options.audience {
+        let valid = match &claims["aud"] {
+            Value::String(aud) => aud == audience,
+            Value::Array(aud) => aud.iter().any(|aud| aud.as_str() == Some(audience)),
+            _ => false,
+        };
+        if !valid {
+            return Err(TokenError::InvalidAudience);
+        }
+    }
+
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use jsonwebtokens::AlgorithmID;
+
+    use crate::skin_convert::key_source::{Jwks, StaticKey};
+    use crate::skin_convert::test_keys::TestKey;
+
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const ISSUER: &str = "https://login.example.com";
+
+    fn options(audience: Option<&str>) -> TokenOptions {
+        TokenOptions {
+            issuer: ISSUER.to_string(),
+            audience: audience.map(str::to_string),
+            clock_skew: 60,
+            now: NOW,
+        }
+    }
+
+    fn jwks(keys: &[(&TestKey, &str)]) -> Jwks {
+        let keys: Vec<Value> = keys.iter().map(|(key, key_id)| key.jwk(key_id)).collect();
+        Jwks::parse(&json!({ "keys": keys }).to_string()).unwrap()
+    }
+
+    fn sign(key: &TestKey, key_id: &str, claims: Value) -> String {
+        key.sign(&json!({ "alg": "ES256", "kid": key_id }), &claims)
+    }
+
+    fn claims() -> Value {
+        json!({ "iss": ISSUER, "aud": "global_api", "sub": "player", "iat": NOW, "exp": NOW + 600 })
+    }
+
+    #[test]
+    fn accepts_valid_token() {
+        let key = TestKey::es256();
+        let token = sign(&key, "key", claims());
+
+        let claims = validate_token(&token, &jwks(&[(&key, "key")]), &options(Some("global_api"))).unwrap();
+        assert_eq!(claims["sub"], "player");
+    }
+
+    #[test]
+    fn selects_key_by_key_id() {
+        let (first, second) = (TestKey::es256(), TestKey::es256());
+        let keys = jwks(&[(&first, "first"), (&second, "second")]);
+
+        let token = sign(&second, "second", claims());
+        assert!(validate_token(&token, &keys, &options(None)).is_ok());
+
+        let token = sign(&second, "third", claims());
+        assert_eq!(validate_token(&token, &keys, &options(None)), Err(TokenError::UnknownKey));
+    }
+
+    #[test]
+    fn accepts_token_without_key_id() {
+        let (first, second) = (TestKey::es256(), TestKey::es256());
+        let keys = jwks(&[(&first, "first"), (&second, "second")]);
+
+        // tokens without a key id are tried against every key
+        let token = second.sign(&json!({ "alg": "ES256" }), &claims());
+        assert!(validate_token(&token, &keys, &options(None)).is_ok());
+
+        let token = TestKey::es256().sign(&json!({ "alg": "ES256" }), &claims());
+        assert_eq!(validate_token(&token, &keys, &options(None)), Err(TokenError::InvalidSignature));
+    }
+
+    #[test]
+    fn accepts_static_key() {
+        let key = TestKey::es384();
+        let token = key.sign(&json!({ "alg": "ES384" }), &claims());
+
+        let static_key = StaticKey::from_der_base64(AlgorithmID::ES384, &key.public_key()).unwrap();
+        assert!(validate_token(&token, &static_key, &options(None)).is_ok());
+    }
+
+    #[test]
+    fn accepts_previous_key_set_after_rotation() {
+        let (old, new) = (TestKey::es256(), TestKey::es256());
+        let mut keys = RotatingKeys::new(DEFAULT_ACTIVE_KEY_SETS);
+        keys.rotate(jwks(&[(&old, "old")]));
+        keys.rotate(jwks(&[(&new, "new")]));
+
+        assert!(validate_token(&sign(&old, "old", claims()), &keys, &options(None)).is_ok());
+        assert!(validate_token(&sign(&new, "new", claims()), &keys, &options(None)).is_ok());
+
+        keys.rotate(jwks(&[(&TestKey::es256(), "newest")]));
+        assert_eq!(validate_token(&sign(&old, "old", claims()), &keys, &options(None)), Err(TokenError::UnknownKey));
+    }
+
+    #[test]
+    fn rejects_invalid_signature() {
+        let key = TestKey::es256();
+        let token = sign(&TestKey::es256(), "key", claims());
+
+        let result = validate_token(&token, &jwks(&[(&key, "key")]), &options(None));
+        assert_eq!(result, Err(TokenError::InvalidSignature));
+    }
+
+    #[test]
+    fn rejects_malformed_token() {
+        let keys = jwks(&[(&TestKey::es256(), "key")]);
+        assert_eq!(validate_token("not a token", &keys, &options(None)), Err(TokenError::MalformedToken));
+    }
+
+    #[test]
+    fn rejects_invalid_time() {
+        let key = TestKey::es256();
+        let keys = jwks(&[(&key, "key")]);
+
+        let mut expired = claims();
+        expired["exp"] = json!(NOW - 61);
+        let token = sign(&key, "key", expired);
+        assert_eq!(validate_token(&token, &keys, &options(None)), Err(TokenError::Expired));
+
+        let mut not_yet_valid = claims();
+        not_yet_valid["nbf"] = json!(NOW + 61);
+        let token = sign(&key, "key", not_yet_valid);
+        assert_eq!(validate_token(&token, &keys, &options(None)), Err(TokenError::NotYetValid));
+
+        let mut without_exp = claims();
+        without_exp.as_object_mut().unwrap().remove("exp");
+        let token = sign(&key, "key", without_exp);
+        assert_eq!(validate_token(&token, &keys, &options(None)), Err(TokenError::MalformedToken));
+    }
+
+    #[test]
+    fn rejects_invalid_issuer() {
+        let key = TestKey::es256();
+        let mut claims = claims();
+        claims["iss"] = json!("https://evil.example.com");
+        let token = sign(&key, "key", claims);
+
+        let result = validate_token(&token, &jwks(&[(&key, "key")]), &options(None));
+        assert_eq!(result, Err(TokenError::InvalidIssuer));
+    }
+
+    #[test]
+    fn validates_audience() {
+        let key = TestKey::es256();
+        let keys = jwks(&[(&key, "key")]);
+
+        let token = sign(&key, "key", claims());
+        assert_eq!(validate_token(&token, &keys, &options(Some("other"))), Err(TokenError::InvalidAudience));
+
+        let mut claims = claims();
+        claims["aud"] = json!(["other", "global_api"]);
+        let token = sign(&key, "key", claims);
+        assert!(validate_token(&token, &keys, &options(Some("global_api"))).is_ok());
+    }
+}
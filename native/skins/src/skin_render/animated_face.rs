@@ -0,0 +1,67 @@
+use image::RgbaImage;
+
+use crate::common::{Offset, RGBA_CHANNELS, SKIN_WIDTH};
+use crate::common::skin::{overlay_inflation, SkinFace, SkinLayer, SkinModel, SkinPart};
+use crate::common::texture::texture_position_face;
+use crate::skin_render::flat_render::{flat_render_padding, render_position, RenderTarget};
+use crate::skin_render::RenderOptions;
+
+/// renders the front of the head for every frame of an animated Bedrock face.
+/// the frames are stacked vertically in the data and every frame uses the skin layout.
+/// returns None when the data can't be split into frames that contain the face,
+/// or when the frames are wider than a (non HD) skin
+pub fn render_animated_face(
+    data: &[u8],
+    data_width: usize,
+    frame_count: usize,
+    layer: &SkinLayer,
+    target_width: usize,
+    options: &RenderOptions
+) -> Option<Vec<RgbaImage>> {
+    if frame_count == 0 || data_width == 0 || data_width > SKIN_WIDTH {
+        return None;
+    }
+
+    let data_height = data.len() / RGBA_CHANNELS / data_width;
+    if !data_height.is_multiple_of(frame_count) {
+        return None;
+    }
+    let frame_height = data_height / frame_count;
+    let frame_size = data_width * frame_height * RGBA_CHANNELS;
+
+    let layers: &[SkinLayer] = match layer {
+        SkinLayer::Both => &[SkinLayer::Bottom, SkinLayer::Top],
+        SkinLayer::Bottom => &[SkinLayer::Bottom],
+        SkinLayer::Top => &[SkinLayer::Top],
+    };
+
+    let mut positions = Vec::with_capacity(layers.len());
+    for layer in layers {
+        let position = texture_position_face(&SkinPart::Head, layer, &SkinFace::Front, &SkinModel::Classic)?;
+        if position.x_offset + position.width > data_width || position.y_offset + position.height > frame_height {
+            return None;
+        }
+        positions.push((layer, position));
+    }
+
+    let scale = target_width / 8;
+    let padding = flat_render_padding(scale, layer, options);
+    let size = (8 * scale + padding * 2) as u32;
+
+    let frames = data.chunks_exact(frame_size).map(|frame| {
+        let mut image = RgbaImage::new(size, size);
+        let target = &mut RenderTarget { image: &mut image, scale, padding };
+
+        for (layer, position) in &positions {
+            let inflation = if *layer == &SkinLayer::Top && options.inflate_overlay {
+                overlay_inflation(&SkinPart::Head)
+            } else {
+                0.0
+            };
+            render_position(frame, data_width, position, target, &Offset::new(0, 0), inflation);
+        }
+        image
+    }).collect();
+
+    Some(frames)
+}
@@ -0,0 +1,59 @@
+use image::imageops::{crop_imm, flip_horizontal, replace};
+use image::RgbaImage;
+
+use crate::common::cape::{cape_texture_position_face, cape_visible_face, CapePart};
+use crate::common::Offset;
+use crate::common::skin::{SkinFace, SkinLayer};
+use crate::skin_render::flat_render::{flat_render_padding, flat_render_scale, render_position, RenderTarget};
+use crate::skin_render::RenderOptions;
+
+/// renders the part like it would be seen from behind the player.
+/// for the elytra both wings are rendered next to each other
+pub fn render_cape(data: &[u8], data_width: usize, part: &CapePart, target_width: usize) -> RgbaImage {
+    let position = cape_texture_position_face(part, &cape_visible_face(part));
+
+    let part_count = if part == &CapePart::Elytra { 2 } else { 1 };
+    let scale = target_width / (position.width * part_count);
+
+    let part_width = position.width * scale;
+    let part_height = position.height * scale;
+
+    let mut image = RgbaImage::new((part_width * part_count) as u32, part_height as u32);
+    render_position(
+        data, data_width, &position,
+        &mut RenderTarget { image: &mut image, scale, padding: 0 },
+        &Offset::new(0, 0), 0.0
+    );
+
+    if part == &CapePart::Elytra {
+        // the right wing is the left wing mirrored
+        let left_wing = crop_imm(&image, 0, 0, part_width as u32, part_height as u32).to_image();
+        let right_wing = flip_horizontal(&left_wing);
+        replace(&mut image, &right_wing, part_width as i64, 0);
+    }
+
+    image
+}
+
+/// draws the cape on top of a render that was made by render_back,
+/// the layer, target_width and options have to be the same as the ones used for render_back
+pub fn render_cape_on_back(
+    target: &mut RgbaImage,
+    data: &[u8],
+    data_width: usize,
+    layer: &SkinLayer,
+    target_width: usize,
+    options: &RenderOptions
+) {
+    let scale = flat_render_scale(target_width);
+    let padding = flat_render_padding(scale, layer, options);
+
+    let position = cape_texture_position_face(&CapePart::Cape, &SkinFace::Front);
+
+    // the cape is a pixel wider than the body on both sides and hangs from the shoulders
+    render_position(
+        data, data_width, &position,
+        &mut RenderTarget { image: target, scale, padding },
+        &Offset::new(3, 8), 0.0
+    );
+}
@@ -1,49 +1,105 @@
 use image::RgbaImage;
 use crate::common::{Offset, OffsetAndDimension};
-use crate::common::skin::{SkinFace, SkinLayer, SkinModel, SkinPart, SkinSection};
+use crate::common::skin::{overlay_inflation, SkinFace, SkinLayer, SkinModel, SkinPart, SkinSection};
 use crate::common::texture::{scale_and_fill_texture, texture_position, texture_position_face};
+use crate::skin_render::RenderOptions;
 
-pub fn render_front(data: &[u8], data_width: usize, layer: &SkinLayer, model: &SkinModel, target_width: usize) -> RgbaImage {
-    let scale = (target_width - (target_width % 16)) / 16;
+/// the image the faces are rendered on, together with the scale of the render
+pub(crate) struct RenderTarget<'a> {
+    pub image: &'a mut RgbaImage,
+    pub scale: usize,
+    /// the amount of (already scaled) pixels around the model,
+    /// used to make room for the inflated overlay
+    pub padding: usize,
+}
+
+pub fn render_front(
+    data: &[u8],
+    data_width: usize,
+    layer: &SkinLayer,
+    model: &SkinModel,
+    target_width: usize,
+    options: &RenderOptions
+) -> RgbaImage {
+    render_flat(&SkinFace::Front, data, data_width, layer, model, target_width, options)
+}
+
+pub fn render_back(
+    data: &[u8],
+    data_width: usize,
+    layer: &SkinLayer,
+    model: &SkinModel,
+    target_width: usize,
+    options: &RenderOptions
+) -> RgbaImage {
+    render_flat(&SkinFace::Back, data, data_width, layer, model, target_width, options)
+}
+
+/// the scale that is used by render_front and render_back for the given target width
+pub(crate) fn flat_render_scale(target_width: usize) -> usize {
+    (target_width - (target_width % 16)) / 16
+}
+
+/// the padding that is used by render_front and render_back for the given scale
+pub(crate) fn flat_render_padding(scale: usize, layer: &SkinLayer, options: &RenderOptions) -> usize {
+    // the hat sticks out 0.5 pixel on every side when the overlay is inflated
+    if options.inflate_overlay && layer != &SkinLayer::Bottom { scale.div_ceil(2) } else { 0 }
+}
+
+fn render_flat(
+    face: &SkinFace,
+    data: &[u8],
+    data_width: usize,
+    layer: &SkinLayer,
+    model: &SkinModel,
+    target_width: usize,
+    options: &RenderOptions
+) -> RgbaImage {
+    let scale = flat_render_scale(target_width);
+    let padding = flat_render_padding(scale, layer, options);
 
-    let mut target = RgbaImage::new((16 * scale) as u32, (32 * scale) as u32);
+    let mut image = RgbaImage::new((16 * scale + padding * 2) as u32, (32 * scale + padding * 2) as u32);
+    let target = &mut RenderTarget { image: &mut image, scale, padding };
+
+    // the left and right side are swapped when looking at the back
+    let arm_offset = if model == &SkinModel::Classic { 0 } else { 1 };
+    let (arm_right_x, arm_left_x, leg_right_x, leg_left_x) = if face == &SkinFace::Front {
+        (arm_offset, 12, 4, 8)
+    } else {
+        (12, arm_offset, 8, 4)
+    };
 
     render_face(
-        &SkinPart::Head, layer, &SkinFace::Front, model,
-        data, data_width, &mut target,
-    &Offset::new(4, 0), scale
+        &SkinPart::Head, layer, face, model, options,
+        data, data_width, target, &Offset::new(4, 0)
     );
 
     render_face(
-        &SkinPart::ArmRight, layer, &SkinFace::Front, model,
-        data, data_width, &mut target,
-    &Offset::new(if model == &SkinModel::Classic { 0 } else { 1 }, 8), scale
+        &SkinPart::ArmRight, layer, face, model, options,
+        data, data_width, target, &Offset::new(arm_right_x, 8)
     );
     render_face(
-        &SkinPart::Body, layer, &SkinFace::Front, model,
-        data, data_width, &mut target,
-    &Offset::new(4, 8), scale
+        &SkinPart::Body, layer, face, model, options,
+        data, data_width, target, &Offset::new(4, 8)
     );
     render_face(
-        &SkinPart::ArmLeft, layer, &SkinFace::Front, model,
-        data, data_width, &mut target,
-    &Offset::new(12, 8), scale
+        &SkinPart::ArmLeft, layer, face, model, options,
+        data, data_width, target, &Offset::new(arm_left_x, 8)
     );
 
     render_face(
-        &SkinPart::LegRight, layer, &SkinFace::Front, model,
-        data, data_width, &mut target,
-    &Offset::new(4, 20), scale
+        &SkinPart::LegRight, layer, face, model, options,
+        data, data_width, target, &Offset::new(leg_right_x, 20)
     );
     render_face(
-        &SkinPart::LegLeft, layer, &SkinFace::Front, model,
-        data, data_width, &mut target,
-    &Offset::new(8, 20), scale
+        &SkinPart::LegLeft, layer, face, model, options,
+        data, data_width, target, &Offset::new(leg_left_x, 20)
     );
 
-    target
+    image
 }
 
+#[allow(dead_code)] // used by the skin debugger
 pub(crate) fn render_section(
     section: SkinSection,
     data: &[u8],
@@ -66,54 +122,70 @@ pub(crate) fn render_section(
     }
 
     if let Some(position) = texture_position(section) {
-        render_position(data, data_width, &position, target, target_offset, target_scale)
+        let target = &mut RenderTarget { image: target, scale: target_scale, padding: 0 };
+        render_position(data, data_width, &position, target, target_offset, 0.0)
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_face(
     part: &SkinPart,
     layer: &SkinLayer,
     face: &SkinFace,
     model: &SkinModel,
+    options: &RenderOptions,
     data: &[u8],
     data_width: usize,
-    target: &mut RgbaImage,
-    target_offset: &Offset,
-    target_scale: usize
+    target: &mut RenderTarget,
+    target_offset: &Offset
 ) {
     if layer == &SkinLayer::Both {
-        render_face(part, &SkinLayer::Bottom, face, model, data, data_width, target, target_offset, target_scale);
-        render_face(part, &SkinLayer::Top, face, model, data, data_width, target, target_offset, target_scale);
+        render_face(part, &SkinLayer::Bottom, face, model, options, data, data_width, target, target_offset);
+        render_face(part, &SkinLayer::Top, face, model, options, data, data_width, target, target_offset);
         return;
     }
 
+    let inflation = if layer == &SkinLayer::Top && options.inflate_overlay {
+        overlay_inflation(part)
+    } else {
+        0.0
+    };
+
     if let Some(position) = texture_position_face(part, layer, face, model) {
-        render_position(data, data_width, &position, target, target_offset, target_scale)
+        render_position(data, data_width, &position, target, target_offset, inflation)
     }
 }
 
-/// the target_offset is the offset without the target_scale
-/// target_offset will be multiplied by target_scale in the method
-fn render_position(
+/// the target_offset is the offset without the target scale and padding,
+/// both will be applied in the method.
+/// inflation is the amount of (unscaled) pixels the face grows on every side
+pub(crate) fn render_position(
     data: &[u8],
     data_width: usize,
     data_position: &OffsetAndDimension,
-    target: &mut RgbaImage,
+    target: &mut RenderTarget,
     target_offset: &Offset,
-    target_scale: usize
+    inflation: f64
 ) {
-    let target_width = target.width() as usize;
-
-    if target_scale == 0 {
+    if target.scale == 0 {
         return;
     }
 
+    let scale = target.scale as f64;
+    let padding = target.padding as f64;
+
+    let start_x = ((target_offset.x_offset as f64 - inflation) * scale + padding).round() as usize;
+    let start_y = ((target_offset.y_offset as f64 - inflation) * scale + padding).round() as usize;
+    let end_x = (((target_offset.x_offset + data_position.width) as f64 + inflation) * scale + padding).round() as usize;
+    let end_y = (((target_offset.y_offset + data_position.height) as f64 + inflation) * scale + padding).round() as usize;
+
     let target_position = OffsetAndDimension {
-        x_offset: target_offset.x_offset * target_scale,
-        y_offset: target_offset.y_offset * target_scale,
-        width: data_position.width * target_scale,
-        height: data_position.height * target_scale,
+        x_offset: start_x,
+        y_offset: start_y,
+        width: end_x - start_x,
+        height: end_y - start_y,
     };
 
-    scale_and_fill_texture(data, target, data_width, target_width, data_position, &target_position);
-}
\ No newline at end of file
+    let target_width = target.image.width() as usize;
+    scale_and_fill_texture(data, target.image, data_width, target_width, data_position, &target_position);
+}
@@ -0,0 +1,339 @@
+use std::collections::HashMap;
+use std::str::from_utf8;
+
+use image::RgbaImage;
+use json::JsonValue;
+
+use crate::common::{OffsetAndDimension, RGBA_CHANNELS, SKIN_HEIGHT, SKIN_WIDTH};
+use crate::common::skin::{FACES, SkinFace, SkinLayer, SkinModel};
+use crate::common::texture::{box_uv_position_face, scale_and_fill_texture};
+use crate::skin_convert::converter::{bone_name_to_skin_section, get_correct_entry};
+use crate::skin_render::isometric_render::{Cube, CubeRotation, model_bounds, render_cubes, render_isometric, Vec3};
+use crate::skin_render::pose::Pose;
+use crate::skin_render::RenderOptions;
+
+/// the largest coordinate, size or inflation of a cube.
+/// Bedrock only renders models of a few blocks (of 16 pixels) large
+const MAX_CUBE_COORDINATE: f64 = 1024.0;
+/// the smallest width of the rendered model, thinner models would need a huge image for the target width
+const MIN_MODEL_WIDTH: f64 = 0.5;
+/// how much higher than wide the rendered model can be
+const MAX_ASPECT_RATIO: f64 = 4.0;
+
+/// a bone that can be the parent of other bones
+struct Bone<'a> {
+    parent: Option<&'a str>,
+    rotation: Option<CubeRotation>,
+}
+
+/// renders a Bedrock skin isometrically using the cubes of its geometry,
+/// the skin data is the raw (RGBA) skin data and the geometry data and resource patch are the decoded json.
+/// Skins without custom geometry are rendered using the default player model.
+/// The overlay isn't inflated by the options, the geometry defines the inflation of every cube instead
+pub fn render_geometry(
+    data: &[u8],
+    data_width: usize,
+    geometry_data: &[u8],
+    resource_patch: &[u8],
+    layer: &SkinLayer,
+    target_width: usize,
+    options: &RenderOptions
+) -> Result<RgbaImage, &'static str> {
+    let geometry_name = geometry_name(resource_patch)?;
+
+    let geometry_data = from_utf8(geometry_data).map_err(|_| "invalid utf-8 data")?;
+    let json = json::parse(geometry_data).map_err(|_| "invalid json")?;
+
+    if json.is_null() {
+        return Ok(render_default_geometry(data, data_width, &geometry_name, layer, target_width, options));
+    }
+
+    let format_version = json["format_version"].as_str()
+        .ok_or("geometry data doesn't have a valid format version")?;
+    let (geometry_entry, tex_width, tex_height) = get_correct_entry(format_version, &json, &geometry_name)?;
+
+    let bones = &geometry_entry["bones"];
+    if !bones.is_array() {
+        return Err("bones isn't an array");
+    }
+
+    let mut bone_map = HashMap::new();
+    for bone in bones.members() {
+        let name = bone["name"].as_str().ok_or("bone doesn't have a name")?;
+        let pivot = vec3(&bone["pivot"]).unwrap_or_default();
+        let rotation = vec3(&bone["rotation"]).map(|angles| to_render_rotation(pivot, angles));
+        bone_map.insert(name, Bone { parent: bone["parent"].as_str(), rotation });
+    }
+
+    // skins can have a higher resolution than the geometry, the uv is in geometry pixels
+    let data_height = data.len() / RGBA_CHANNELS / data_width;
+    let uv_scale = (data_width as f64 / tex_width as f64, data_height as f64 / tex_height as f64);
+
+    let mut base_cubes = Vec::new();
+    let mut overlay_cubes = Vec::new();
+
+    for bone in bones.members() {
+        let name = bone["name"].as_str().ok_or("bone doesn't have a name")?;
+        if bone["neverRender"].as_bool().unwrap_or(false) || bone["never_render"].as_bool().unwrap_or(false) {
+            continue;
+        }
+
+        // bones that aren't part of the default player model are seen as part of the bottom layer
+        let bone_layer = bone_name_to_skin_section(name).map_or(SkinLayer::Bottom, |section| section.1);
+        if layer != &SkinLayer::Both && layer != &bone_layer {
+            continue;
+        }
+
+        let cubes = &bone["cubes"];
+        // poly mesh bones aren't supported (yet)
+        if !cubes.is_array() {
+            continue;
+        }
+
+        let bone_rotations = bone_rotations(&bone_map, name);
+        let bone_inflate = bone["inflate"].as_f64().unwrap_or(0.0);
+        let bone_mirror = bone["mirror"].as_bool().unwrap_or(false);
+
+        for cube in cubes.members() {
+            let origin = vec3(&cube["origin"]).ok_or("cube doesn't have a valid origin")?;
+            let size = vec3(&cube["size"]).ok_or("cube doesn't have a valid size")?;
+            let inflate = cube["inflate"].as_f64().unwrap_or(bone_inflate);
+            let mirror = cube["mirror"].as_bool().unwrap_or(bone_mirror);
+
+            if ![origin, size].iter().all(|vec| valid_coordinate(vec.x) && valid_coordinate(vec.y) && valid_coordinate(vec.z))
+                || !valid_coordinate(inflate) {
+                return Err("cube is too large");
+            }
+
+            let mut rotations = Vec::with_capacity(bone_rotations.len() + 1);
+            if let Some(angles) = vec3(&cube["rotation"]) {
+                let pivot = vec3(&cube["pivot"]).unwrap_or(origin + size * 0.5);
+                rotations.push(to_render_rotation(pivot, angles));
+            }
+            rotations.extend_from_slice(&bone_rotations);
+
+            let faces = cube_faces(&cube["uv"], size, mirror, uv_scale)?;
+
+            let origin = origin - Vec3::new(inflate, inflate, inflate);
+            let size = size + Vec3::new(inflate, inflate, inflate) * 2.0;
+
+            let cube = Cube {
+                // the player looks towards -z in Bedrock
+                origin: Vec3::new(origin.x, origin.y, -(origin.z + size.z)),
+                size,
+                rotations,
+                faces,
+                mirror: mirror && cube["uv"].is_array(),
+            };
+
+            if bone_layer == SkinLayer::Top {
+                overlay_cubes.push(cube);
+            } else {
+                base_cubes.push(cube);
+            }
+        }
+    }
+
+    // the overlay has to be rendered after the base, so that transparent pixels blend correctly
+    base_cubes.append(&mut overlay_cubes);
+
+    // cubes without any width don't have bounds
+    let too_thin = model_bounds(&base_cubes).map_or(!base_cubes.is_empty(), |bounds| {
+        bounds.width() < MIN_MODEL_WIDTH || bounds.height() > bounds.width() * MAX_ASPECT_RATIO
+    });
+    if too_thin {
+        return Err("geometry is too thin to render");
+    }
+    Ok(render_cubes(&base_cubes, data, data_width, target_width, options))
+}
+
+fn geometry_name(resource_patch: &[u8]) -> Result<String, &'static str> {
+    let resource_patch = from_utf8(resource_patch).map_err(|_| "invalid utf-8 data")?;
+    let resource_patch = json::parse(resource_patch).map_err(|_| "invalid json")?;
+
+    let name = resource_patch["geometry"]["default"].as_str().ok_or("resource patch doesn't have a geometry")?;
+    Ok(String::from(name))
+}
+
+fn render_default_geometry(
+    data: &[u8],
+    data_width: usize,
+    geometry_name: &str,
+    layer: &SkinLayer,
+    target_width: usize,
+    options: &RenderOptions
+) -> RgbaImage {
+    let model = if geometry_name.ends_with("Slim") { SkinModel::Slim } else { SkinModel::Classic };
+
+    let data_height = data.len() / RGBA_CHANNELS / data_width;
+    if data_width == SKIN_WIDTH && data_height == SKIN_HEIGHT {
+        return render_isometric(data, data_width, layer, &model, &Pose::Idle, target_width, options);
+    }
+
+    // the default model uses the texture positions of a 64x64 skin
+    let mut scaled = vec![0; SKIN_WIDTH * SKIN_HEIGHT * RGBA_CHANNELS];
+    scale_and_fill_texture(
+        data, &mut scaled, data_width, SKIN_WIDTH,
+        &OffsetAndDimension::new(0, 0, data_width, data_height),
+        &OffsetAndDimension::new(0, 0, SKIN_WIDTH, SKIN_HEIGHT)
+    );
+    render_isometric(&scaled, SKIN_WIDTH, layer, &model, &Pose::Idle, target_width, options)
+}
+
+/// the rotation of the bone and the rotation of all its parents
+fn bone_rotations(bones: &HashMap<&str, Bone>, name: &str) -> Vec<CubeRotation> {
+    let mut rotations = Vec::new();
+    let mut current = Some(name);
+
+    // bones.len() limits the depth, so bones that are (indirectly) their own parent can't loop forever
+    for _ in 0..bones.len() {
+        let Some(bone) = current.and_then(|name| bones.get(name)) else { break };
+        if let Some(rotation) = bone.rotation {
+            rotations.push(rotation);
+        }
+        current = bone.parent;
+    }
+    rotations
+}
+
+/// the texture position of every face, in the same order as FACES.
+/// the uv is either the offset of a box uv or an object with the uv of every face
+fn cube_faces(uv: &JsonValue, size: Vec3, mirror: bool, uv_scale: (f64, f64))
+    -> Result<[Option<OffsetAndDimension>; 6], &'static str> {
+
+    let mut faces = if uv.is_object() {
+        FACES.map(|face| per_face_uv(&uv[bedrock_face_name(&face)]))
+    } else {
+        let (u, v) = if uv.is_null() {
+            (0.0, 0.0)
+        } else {
+            let offset = (uv[0].as_f64(), uv[1].as_f64());
+            let (Some(u), Some(v)) = offset else { return Err("cube's uv isn't valid") };
+            if !valid_coordinate(u) || !valid_coordinate(v) {
+                return Err("cube's uv isn't valid");
+            }
+            (u, v)
+        };
+
+        let (width, height, depth) = (size.x.floor() as usize, size.y.floor() as usize, size.z.floor() as usize);
+        let mut faces = FACES.map(|face| {
+            Some(box_uv_position_face(u.max(0.0) as usize, v.max(0.0) as usize, width, height, depth, &face))
+        });
+        if mirror {
+            // a mirrored cube also has its right and left side swapped
+            faces.swap(2, 4);
+        }
+        faces
+    };
+
+    if uv_scale != (1.0, 1.0) {
+        for face in faces.iter_mut().flatten() {
+            *face = OffsetAndDimension::new(
+                (face.x_offset as f64 * uv_scale.0).round() as usize,
+                (face.y_offset as f64 * uv_scale.1).round() as usize,
+                (face.width as f64 * uv_scale.0).round() as usize,
+                (face.height as f64 * uv_scale.1).round() as usize,
+            );
+        }
+    }
+    Ok(faces)
+}
+
+/// the uv of a single face, a negative size means that the face is flipped.
+/// flipped faces are rendered without flipping them
+fn per_face_uv(face: &JsonValue) -> Option<OffsetAndDimension> {
+    let u = face["uv"][0].as_f64()?;
+    let v = face["uv"][1].as_f64()?;
+    let width = face["uv_size"][0].as_f64()?;
+    let height = face["uv_size"][1].as_f64()?;
+    if ![u, v, width, height].into_iter().all(valid_coordinate) {
+        return None;
+    }
+
+    Some(OffsetAndDimension::new(
+        u.min(u + width).max(0.0) as usize,
+        v.min(v + height).max(0.0) as usize,
+        width.abs() as usize,
+        height.abs() as usize,
+    ))
+}
+
+fn bedrock_face_name(face: &SkinFace) -> &'static str {
+    // the player looks north and the right side of the player is towards -x (west)
+    match face {
+        SkinFace::Top => "up",
+        SkinFace::Bottom => "down",
+        SkinFace::Right => "west",
+        SkinFace::Front => "north",
+        SkinFace::Left => "east",
+        SkinFace::Back => "south",
+    }
+}
+
+/// Bedrock's z axis points the other way than the render space's z axis,
+/// which flips the direction of the y and z rotation as well
+fn to_render_rotation(pivot: Vec3, angles: Vec3) -> CubeRotation {
+    CubeRotation {
+        pivot: Vec3::new(pivot.x, pivot.y, -pivot.z),
+        angles: Vec3::new(angles.x, -angles.y, -angles.z),
+    }
+}
+
+fn valid_coordinate(value: f64) -> bool {
+    value.is_finite() && value.abs() <= MAX_CUBE_COORDINATE
+}
+
+fn vec3(value: &JsonValue) -> Option<Vec3> {
+    if !value.is_array() || value.len() != 3 {
+        return None;
+    }
+    Some(Vec3::new(value[0].as_f64()?, value[1].as_f64()?, value[2].as_f64()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::skin::STEVE_SKIN;
+
+    use super::*;
+
+    const RESOURCE_PATCH: &[u8] = br#"{"geometry":{"default":"geometry.test"}}"#;
+
+    fn render(cubes: &str) -> Result<RgbaImage, &'static str> {
+        let geometry = format!(
+            r#"{{"format_version":"1.12.0","minecraft:geometry":[{{
+                "description":{{"identifier":"geometry.test","texture_width":64,"texture_height":64}},
+                "bones":[{{"name":"body","pivot":[0,24,0],"cubes":{}}}]
+            }}]}}"#,
+            cubes
+        );
+        render_geometry(
+            &STEVE_SKIN, SKIN_WIDTH, geometry.as_bytes(), RESOURCE_PATCH,
+            &SkinLayer::Both, 128, &RenderOptions::default()
+        )
+    }
+
+    #[test]
+    fn renders_cubes() {
+        let image = render(r#"[{"origin":[-4,12,-2],"size":[8,12,4],"uv":[16,16]}]"#).unwrap();
+        assert_eq!(image.width(), 128);
+        assert!(image.height() > 0);
+    }
+
+    #[test]
+    fn rejects_thin_geometry() {
+        assert!(render(r#"[{"origin":[0,0,0],"size":[0.00001,200,0],"uv":[0,0]}]"#).is_err());
+        assert!(render(r#"[{"origin":[0,0,0],"size":[0,8,0],"uv":[0,0]}]"#).is_err());
+    }
+
+    #[test]
+    fn rejects_large_cubes() {
+        assert!(render(r#"[{"origin":[0,0,0],"size":[1e300,8,8],"uv":[0,0]}]"#).is_err());
+        assert!(render(r#"[{"origin":[0,0,0],"size":[8,8,8],"inflate":1e9,"uv":[0,0]}]"#).is_err());
+        assert!(render(r#"[{"origin":[0,0,0],"size":[8,8,8],"uv":[1e300,1e300]}]"#).is_err());
+
+        // faces with an invalid uv aren't rendered, like faces without a uv
+        assert!(render(
+            r#"[{"origin":[0,0,0],"size":[8,8,8],"uv":{"north":{"uv":[1e300,0],"uv_size":[1e300,8]}}}]"#
+        ).is_ok());
+    }
+}
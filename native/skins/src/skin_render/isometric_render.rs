@@ -0,0 +1,461 @@
+use std::ops::{Add, Mul, Sub};
+
+use image::RgbaImage;
+
+use crate::common::{OffsetAndDimension, RGBA_CHANNELS};
+use crate::common::skin::{FACES, overlay_inflation, SkinFace, SkinLayer, SkinModel, SkinPart};
+use crate::common::texture::texture_position_face;
+use crate::skin_render::pose::{part_rotation, PartRotation, Pose};
+use crate::skin_render::{blend_pixel, RenderOptions};
+
+/// how far the model is turned, so that both the front and the right side are visible
+const CAMERA_YAW: f64 = 30.0;
+/// how far the camera looks down on the model, so that the top is visible
+const CAMERA_PITCH: f64 = 20.0;
+
+/// the brightness of faces that face up, down, north/south and east/west in Minecraft
+const TOP_BRIGHTNESS: f64 = 1.0;
+const BOTTOM_BRIGHTNESS: f64 = 0.5;
+const Z_AXIS_BRIGHTNESS: f64 = 0.8;
+const X_AXIS_BRIGHTNESS: f64 = 0.6;
+
+/// how much darker the outer pixels of a face become with ambient occlusion
+const AMBIENT_OCCLUSION_STRENGTH: f64 = 0.25;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl Vec3 {
+    pub const fn new(x: f64, y: f64, z: f64) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    fn cross(self, other: Vec3) -> Vec3 {
+        Vec3::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    fn rotate_x(self, degrees: f64) -> Vec3 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Vec3::new(self.x, self.y * cos - self.z * sin, self.y * sin + self.z * cos)
+    }
+
+    fn rotate_y(self, degrees: f64) -> Vec3 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Vec3::new(self.x * cos + self.z * sin, self.y, -self.x * sin + self.z * cos)
+    }
+
+    fn rotate_z(self, degrees: f64) -> Vec3 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        Vec3::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos, self.z)
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f64> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, scale: f64) -> Vec3 {
+        Vec3::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+/// a textured cube in render space: y is up, the player looks towards +z
+/// and the right side of the player is towards -x. All sizes are in texture pixels
+pub struct Cube {
+    /// the corner with the lowest x, y and z
+    pub origin: Vec3,
+    pub size: Vec3,
+    /// the rotations of the cube, applied in the given order.
+    /// cubes of a model with a hierarchy have the rotation of every parent after their own
+    pub rotations: Vec<CubeRotation>,
+    /// the texture position of every face, in the same order as FACES.
+    /// faces without a position aren't rendered
+    pub faces: [Option<OffsetAndDimension>; 6],
+    /// whether the textures should be mirrored horizontally
+    pub mirror: bool,
+}
+
+/// a rotation around a pivot point
+#[derive(Clone, Copy)]
+pub struct CubeRotation {
+    pub pivot: Vec3,
+    /// the rotation in degrees, applied in z, y, x order
+    pub angles: Vec3,
+}
+
+impl Cube {
+    /// converts a cube from Java's model space (y is down, the player looks towards -z)
+    /// to a cube in render space. The origin is relative to the pivot, like it is in Java
+    fn from_java(
+        pivot: Vec3,
+        origin: Vec3,
+        size: Vec3,
+        inflation: f64,
+        rotation: &PartRotation,
+        faces: [Option<OffsetAndDimension>; 6]
+    ) -> Cube {
+        let origin = pivot + origin - Vec3::new(inflation, inflation, inflation);
+        let size = size + Vec3::new(inflation, inflation, inflation) * 2.0;
+
+        Cube {
+            origin: Vec3::new(origin.x, -(origin.y + size.y), -(origin.z + size.z)),
+            size,
+            rotations: vec![CubeRotation {
+                pivot: Vec3::new(pivot.x, -pivot.y, -pivot.z),
+                // flipping the y and z axis flips the direction of the y and z rotation as well
+                angles: Vec3::new(rotation.x, -rotation.y, -rotation.z),
+            }],
+            faces,
+            mirror: false,
+        }
+    }
+
+    /// the corner the face starts at (texture u = 0, v = 0) and the vectors
+    /// that point towards u = width and v = height, before rotating the cube
+    fn face_vectors(&self, face: &SkinFace) -> (Vec3, Vec3, Vec3) {
+        let Vec3 { x, y, z } = self.origin;
+        let Vec3 { x: width, y: height, z: depth } = self.size;
+
+        match face {
+            SkinFace::Top => (Vec3::new(x, y + height, z), Vec3::new(width, 0.0, 0.0), Vec3::new(0.0, 0.0, depth)),
+            SkinFace::Bottom => (Vec3::new(x, y, z + depth), Vec3::new(width, 0.0, 0.0), Vec3::new(0.0, 0.0, -depth)),
+            SkinFace::Right => (Vec3::new(x, y + height, z), Vec3::new(0.0, 0.0, depth), Vec3::new(0.0, -height, 0.0)),
+            SkinFace::Front => (Vec3::new(x, y + height, z + depth), Vec3::new(width, 0.0, 0.0), Vec3::new(0.0, -height, 0.0)),
+            SkinFace::Left => (Vec3::new(x + width, y + height, z + depth), Vec3::new(0.0, 0.0, -depth), Vec3::new(0.0, -height, 0.0)),
+            SkinFace::Back => (Vec3::new(x + width, y + height, z), Vec3::new(-width, 0.0, 0.0), Vec3::new(0.0, -height, 0.0)),
+        }
+    }
+
+    /// rotates a point of this cube around its pivots and then moves it into camera space
+    fn transform(&self, point: Vec3) -> Vec3 {
+        let rotated = self.rotations.iter().fold(point, |point, rotation| {
+            rotate(point - rotation.pivot, rotation.angles) + rotation.pivot
+        });
+
+        to_camera_space(rotated)
+    }
+
+    /// rotates a direction like the cube is rotated, directions aren't affected by the pivots
+    fn rotate_direction(&self, direction: Vec3) -> Vec3 {
+        self.rotations.iter().fold(direction, |direction, rotation| rotate(direction, rotation.angles))
+    }
+
+    /// same as transform, but for directions
+    fn transform_direction(&self, direction: Vec3) -> Vec3 {
+        to_camera_space(self.rotate_direction(direction))
+    }
+}
+
+fn rotate(point: Vec3, angles: Vec3) -> Vec3 {
+    point.rotate_x(angles.x).rotate_y(angles.y).rotate_z(angles.z)
+}
+
+fn to_camera_space(point: Vec3) -> Vec3 {
+    point.rotate_y(CAMERA_YAW).rotate_x(CAMERA_PITCH)
+}
+
+pub fn render_isometric(
+    data: &[u8],
+    data_width: usize,
+    layer: &SkinLayer,
+    model: &SkinModel,
+    pose: &Pose,
+    target_width: usize,
+    options: &RenderOptions
+) -> RgbaImage {
+    let mut cubes = Vec::new();
+
+    // the top layer has to be rendered after the bottom layer, so that transparent pixels blend correctly
+    if layer != &SkinLayer::Top {
+        add_player_cubes(&mut cubes, &SkinLayer::Bottom, model, pose, options);
+    }
+    if layer != &SkinLayer::Bottom {
+        add_player_cubes(&mut cubes, &SkinLayer::Top, model, pose, options);
+    }
+
+    render_cubes(&cubes, data, data_width, target_width, options)
+}
+
+fn add_player_cubes(cubes: &mut Vec<Cube>, layer: &SkinLayer, model: &SkinModel, pose: &Pose, options: &RenderOptions) {
+    let arm_width = if model == &SkinModel::Classic { 4.0 } else { 3.0 };
+    let arm_pivot_y = if model == &SkinModel::Classic { 2.0 } else { 2.5 };
+
+    // pivot, origin (relative to the pivot) and size of every part, like Java's PlayerModel
+    let parts = [
+        (SkinPart::Head, Vec3::new(0.0, 0.0, 0.0), Vec3::new(-4.0, -8.0, -4.0), Vec3::new(8.0, 8.0, 8.0)),
+        (SkinPart::Body, Vec3::new(0.0, 0.0, 0.0), Vec3::new(-4.0, 0.0, -2.0), Vec3::new(8.0, 12.0, 4.0)),
+        (SkinPart::ArmRight, Vec3::new(-5.0, arm_pivot_y, 0.0), Vec3::new(1.0 - arm_width, -2.0, -2.0), Vec3::new(arm_width, 12.0, 4.0)),
+        (SkinPart::ArmLeft, Vec3::new(5.0, arm_pivot_y, 0.0), Vec3::new(-1.0, -2.0, -2.0), Vec3::new(arm_width, 12.0, 4.0)),
+        (SkinPart::LegRight, Vec3::new(-1.9, 12.0, 0.0), Vec3::new(-2.0, 0.0, -2.0), Vec3::new(4.0, 12.0, 4.0)),
+        (SkinPart::LegLeft, Vec3::new(1.9, 12.0, 0.0), Vec3::new(-2.0, 0.0, -2.0), Vec3::new(4.0, 12.0, 4.0)),
+    ];
+
+    for (part, pivot, origin, size) in parts {
+        let inflation = if layer == &SkinLayer::Top && options.inflate_overlay {
+            overlay_inflation(&part)
+        } else {
+            0.0
+        };
+
+        let faces = FACES.map(|face| texture_position_face(&part, layer, &face, model));
+        let rotation = part_rotation(pose, &part);
+
+        cubes.push(Cube::from_java(pivot, origin, size, inflation, &rotation, faces));
+    }
+}
+
+/// the area of the image that the cubes cover, in texture pixels
+pub struct ModelBounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl ModelBounds {
+    pub fn width(&self) -> f64 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f64 {
+        self.max_y - self.min_y
+    }
+}
+
+/// the bounds of the cubes after moving them to image space,
+/// returns None when there are no cubes or when they don't have a width
+pub fn model_bounds(cubes: &[Cube]) -> Option<ModelBounds> {
+    let mut min_x = f64::MAX;
+    let mut min_y = f64::MAX;
+    let mut max_x = f64::MIN;
+    let mut max_y = f64::MIN;
+
+    for cube in cubes {
+        for corner in 0..8 {
+            let offset = Vec3::new(
+                if corner & 1 == 0 { 0.0 } else { cube.size.x },
+                if corner & 2 == 0 { 0.0 } else { cube.size.y },
+                if corner & 4 == 0 { 0.0 } else { cube.size.z },
+            );
+            let point = cube.transform(cube.origin + offset);
+            min_x = min_x.min(point.x);
+            max_x = max_x.max(point.x);
+            // images have their y axis pointing down
+            min_y = min_y.min(-point.y);
+            max_y = max_y.max(-point.y);
+        }
+    }
+
+    if cubes.is_empty() || max_x <= min_x {
+        return None;
+    }
+    Some(ModelBounds { min_x, min_y, max_x, max_y })
+}
+
+/// renders the cubes in the order they're given, the whole model will be target_width wide
+pub fn render_cubes(
+    cubes: &[Cube],
+    data: &[u8],
+    data_width: usize,
+    target_width: usize,
+    options: &RenderOptions
+) -> RgbaImage {
+    let Some(ModelBounds { min_x, min_y, max_x, max_y }) = model_bounds(cubes) else {
+        return RgbaImage::new(0, 0);
+    };
+
+    let scale = target_width as f64 / (max_x - min_x);
+    let height = ((max_y - min_y) * scale).ceil() as usize;
+
+    let mut image = RgbaImage::new(target_width as u32, height as u32);
+    let mut depth_buffer = vec![f64::MIN; target_width * height];
+
+    let data_height = data.len() / RGBA_CHANNELS / data_width;
+
+    for cube in cubes {
+        for (face, texture) in FACES.iter().zip(cube.faces.iter()) {
+            let Some(texture) = texture else { continue };
+            if texture.width == 0 || texture.height == 0 {
+                continue;
+            }
+
+            let (origin, u_vector, v_vector) = cube.face_vectors(face);
+
+            // skip the faces that look away from the camera
+            let normal = cube.rotate_direction(v_vector.cross(u_vector));
+            if to_camera_space(normal).z <= 0.0 {
+                continue;
+            }
+
+            let brightness = if options.shading { face_brightness(normal) } else { 1.0 };
+
+            // move everything to image space
+            let origin = cube.transform(origin);
+            let u_vector = cube.transform_direction(u_vector);
+            let v_vector = cube.transform_direction(v_vector);
+
+            let origin_x = (origin.x - min_x) * scale;
+            let origin_y = (-origin.y - min_y) * scale;
+            let (u_x, u_y) = (u_vector.x * scale, -u_vector.y * scale);
+            let (v_x, v_y) = (v_vector.x * scale, -v_vector.y * scale);
+
+            let determinant = u_x * v_y - u_y * v_x;
+            if determinant.abs() < f64::EPSILON {
+                continue;
+            }
+
+            let corners_x = [origin_x, origin_x + u_x, origin_x + v_x, origin_x + u_x + v_x];
+            let corners_y = [origin_y, origin_y + u_y, origin_y + v_y, origin_y + u_y + v_y];
+            let start_x = corners_x.iter().cloned().fold(f64::MAX, f64::min).floor().max(0.0) as usize;
+            let start_y = corners_y.iter().cloned().fold(f64::MAX, f64::min).floor().max(0.0) as usize;
+            let end_x = (corners_x.iter().cloned().fold(f64::MIN, f64::max).ceil() as usize).min(target_width);
+            let end_y = (corners_y.iter().cloned().fold(f64::MIN, f64::max).ceil() as usize).min(height);
+
+            for y in start_y..end_y {
+                for x in start_x..end_x {
+                    // find where the center of this pixel is on the face
+                    let delta_x = x as f64 + 0.5 - origin_x;
+                    let delta_y = y as f64 + 0.5 - origin_y;
+                    let u = (delta_x * v_y - delta_y * v_x) / determinant;
+                    let v = (u_x * delta_y - u_y * delta_x) / determinant;
+
+                    if !(0.0..1.0).contains(&u) || !(0.0..1.0).contains(&v) {
+                        continue;
+                    }
+
+                    let mut texture_x = ((u * texture.width as f64) as usize).min(texture.width - 1);
+                    let texture_y = ((v * texture.height as f64) as usize).min(texture.height - 1);
+                    if cube.mirror {
+                        texture_x = texture.width - 1 - texture_x;
+                    }
+
+                    let source_x = texture.x_offset + texture_x;
+                    let source_y = texture.y_offset + texture_y;
+                    if source_x >= data_width || source_y >= data_height {
+                        continue;
+                    }
+
+                    let source_pixel = (source_y * data_width + source_x) * RGBA_CHANNELS;
+                    let alpha = data[source_pixel + 3];
+                    if alpha == 0 {
+                        continue;
+                    }
+
+                    let depth = origin.z + u * u_vector.z + v * v_vector.z;
+                    let depth_index = y * target_width + x;
+                    // faces that are rendered later win when they're at the same depth,
+                    // that way the top layer is visible when it isn't inflated
+                    if depth < depth_buffer[depth_index] {
+                        continue;
+                    }
+                    depth_buffer[depth_index] = depth;
+
+                    let mut source = [0; RGBA_CHANNELS];
+                    source.copy_from_slice(&data[source_pixel..source_pixel + RGBA_CHANNELS]);
+
+                    let mut shade = brightness;
+                    if options.ambient_occlusion {
+                        shade *= edge_occlusion(u, v, texture);
+                    }
+                    if shade < 1.0 {
+                        for channel in source.iter_mut().take(3) {
+                            *channel = (*channel as f64 * shade).round() as u8;
+                        }
+                    }
+
+                    let target = image.get_pixel_mut(x as u32, y as u32);
+                    blend_pixel(&mut target.0, &source);
+                }
+            }
+        }
+    }
+
+    image
+}
+
+/// the brightness of a face with the given (not normalized) normal.
+/// faces that are rotated get a mix of the brightness of the sides they're facing
+fn face_brightness(normal: Vec3) -> f64 {
+    let length_squared = normal.x * normal.x + normal.y * normal.y + normal.z * normal.z;
+    if length_squared == 0.0 {
+        return 1.0;
+    }
+
+    let vertical_brightness = if normal.y > 0.0 { TOP_BRIGHTNESS } else { BOTTOM_BRIGHTNESS };
+    (normal.x * normal.x * X_AXIS_BRIGHTNESS
+        + normal.y * normal.y * vertical_brightness
+        + normal.z * normal.z * Z_AXIS_BRIGHTNESS) / length_squared
+}
+
+/// darkens the outer texture pixel of every side of a face.
+/// u and v are the position on the face, between 0 and 1
+fn edge_occlusion(u: f64, v: f64, texture: &OffsetAndDimension) -> f64 {
+    let width = texture.width as f64;
+    let height = texture.height as f64;
+
+    let edge_distance = (u * width)
+        .min((1.0 - u) * width)
+        .min(v * height)
+        .min((1.0 - v) * height);
+
+    if edge_distance >= 1.0 {
+        return 1.0;
+    }
+    1.0 - AMBIENT_OCCLUSION_STRENGTH * (1.0 - edge_distance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    /// a green skin with a red right side and a blue left side of the torso, at their box uv positions
+    fn torso_sides_skin() -> Vec<u8> {
+        let mut skin = [0, 255, 0, 255].repeat(64 * 64);
+        for y in 20..32 {
+            for x in 0..4 {
+                skin[((y * 64) + 16 + x) * RGBA_CHANNELS..][..RGBA_CHANNELS].copy_from_slice(&RED);
+                skin[((y * 64) + 28 + x) * RGBA_CHANNELS..][..RGBA_CHANNELS].copy_from_slice(&BLUE);
+            }
+        }
+        skin
+    }
+
+    #[test]
+    fn renders_right_side_of_torso() {
+        let mut cubes = Vec::new();
+        add_player_cubes(&mut cubes, &SkinLayer::Bottom, &SkinModel::Classic, &Pose::Idle, &RenderOptions::default());
+        // the arm would cover the side of the torso
+        let body = cubes.remove(1);
+
+        let render = render_cubes(&[body], &torso_sides_skin(), 64, 128, &RenderOptions::default());
+        let pixels: Vec<&[u8]> = render.pixels().map(|pixel| &pixel.0[..]).collect();
+        assert!(pixels.contains(&&RED[..]));
+        assert!(!pixels.contains(&&BLUE[..]));
+    }
+}
@@ -1,2 +1,128 @@
+use image::Rgba;
+use rustler::{atoms, Decoder, Error, NifResult, Term};
+use rustler::types::tuple::get_tuple;
+
+use crate::common::encoder::OutputFormat;
+use crate::common::RGBA_CHANNELS;
+use crate::rustler_utils::map_get_optional;
+
+pub mod animated_face;
+pub mod cape_render;
 pub mod flat_render;
+pub mod geometry_render;
+pub mod isometric_render;
+pub mod pose;
+pub mod post_process;
+pub mod profile_card;
+pub mod render_cache;
+pub mod render_request;
+pub mod sprite_sheet;
+
+/// the largest target width of every render, larger renders would take too much memory and time
+pub const MAX_TARGET_WIDTH: usize = 2048;
+
+atoms! {
+    inflate_overlay,
+    shading,
+    ambient_occlusion,
+    background,
+    drop_shadow,
+    outline,
+    format,
+}
+
+/// options that can be given to every render, all of them are optional on the Elixir side
+#[derive(Default, Hash)]
+pub struct RenderOptions {
+    /// draw the overlay (top) layer slightly larger than the base layer, like Minecraft does
+    pub inflate_overlay: bool,
+    /// darken faces based on the direction they're facing, like Minecraft does.
+    /// only used by renders that show more than one side of the model
+    pub shading: bool,
+    /// darken the edges of every face, only used by renders that show more than one side of the model
+    pub ambient_occlusion: bool,
+    pub background: Background,
+    /// draw a shadow below the feet of the model
+    pub drop_shadow: bool,
+    /// the color of the 1 pixel outline around the model, None for no outline
+    pub outline: Option<Rgba<u8>>,
+    /// the format the render is encoded in
+    pub format: OutputFormat,
+}
+
+#[derive(Default, PartialEq, Eq, Hash)]
+pub enum Background {
+    #[default]
+    Transparent,
+    Solid(Rgba<u8>),
+    /// a vertical gradient from the first (top) color to the second (bottom) color
+    Gradient(Rgba<u8>, Rgba<u8>),
+}
+
+impl<'a> Decoder<'a> for RenderOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut options = RenderOptions::default();
+        if let Some(inflate) = map_get_optional(term, inflate_overlay())? {
+            options.inflate_overlay = inflate;
+        }
+        if let Some(shade) = map_get_optional(term, shading())? {
+            options.shading = shade;
+        }
+        if let Some(occlusion) = map_get_optional(term, ambient_occlusion())? {
+            options.ambient_occlusion = occlusion;
+        }
+        if let Some(background_term) = map_get_optional::<Option<Term>>(term, background())?.flatten() {
+            options.background = decode_background(background_term)?;
+        }
+        if let Some(shadow) = map_get_optional(term, drop_shadow())? {
+            options.drop_shadow = shadow;
+        }
+        if let Some(outline_term) = map_get_optional::<Option<Term>>(term, outline())?.flatten() {
+            options.outline = Some(decode_color(outline_term)?);
+        }
+        if let Some(output_format) = map_get_optional(term, format())? {
+            options.format = output_format;
+        }
+        Ok(options)
+    }
+}
+
+/// either a single color or a tuple of two colors (top and bottom) for a gradient
+fn decode_background(term: Term) -> NifResult<Background> {
+    let elements = get_tuple(term)?;
+    if elements.len() == 2 {
+        return Ok(Background::Gradient(decode_color(elements[0])?, decode_color(elements[1])?));
+    }
+    Ok(Background::Solid(decode_color(term)?))
+}
+
+/// colors are either {r, g, b} or {r, g, b, a} tuples
+fn decode_color(term: Term) -> NifResult<Rgba<u8>> {
+    if let Ok((r, g, b)) = term.decode::<(u8, u8, u8)>() {
+        return Ok(Rgba([r, g, b, 255]));
+    }
+    if let Ok((r, g, b, a)) = term.decode::<(u8, u8, u8, u8)>() {
+        return Ok(Rgba([r, g, b, a]));
+    }
+    Err(Error::BadArg)
+}
+
+/// draws the source pixel over the target pixel
+pub(crate) fn blend_pixel(target: &mut [u8; RGBA_CHANNELS], source: &[u8]) {
+    let source_alpha = source[3] as u32;
+    if source_alpha == 255 {
+        target.copy_from_slice(source);
+        return;
+    }
+
+    let target_alpha = target[3] as u32 * (255 - source_alpha) / 255;
+    let alpha = source_alpha + target_alpha;
+    if alpha == 0 {
+        return;
+    }
 
+    for i in 0..3 {
+        target[i] = ((source[i] as u32 * source_alpha + target[i] as u32 * target_alpha) / alpha) as u8;
+    }
+    target[3] = alpha as u8;
+}
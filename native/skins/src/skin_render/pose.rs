@@ -0,0 +1,53 @@
+use rustler::NifUnitEnum;
+
+use crate::common::skin::SkinPart;
+
+/// the poses that the isometric renderer can render a player in
+#[derive(PartialEq, Eq, Hash, Default, NifUnitEnum)]
+pub enum Pose {
+    /// the default standing pose
+    #[default]
+    Idle,
+    /// halfway through a step, with the arms swinging opposite to the legs
+    Walking,
+    /// the right arm is raised above the head
+    Waving,
+    /// both arms are stretched out sideways
+    ArmsOut,
+}
+
+/// the rotation (in degrees) of a part around its pivot.
+/// these use the same axis and order (z, y, x) as Minecraft Java's ModelPart, so values from
+/// the Java animations (e.g. HumanoidModel#setupAnim) can be used directly
+#[derive(Default)]
+pub struct PartRotation {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl PartRotation {
+    pub fn new(x: f64, y: f64, z: f64) -> PartRotation {
+        PartRotation { x, y, z }
+    }
+}
+
+pub fn part_rotation(pose: &Pose, part: &SkinPart) -> PartRotation {
+    let new = PartRotation::new;
+    match (pose, part) {
+        (Pose::Walking, SkinPart::Head) => new(0.0, 8.0, 0.0),
+        (Pose::Walking, SkinPart::ArmRight) => new(-35.0, 0.0, 0.0),
+        (Pose::Walking, SkinPart::ArmLeft) => new(35.0, 0.0, 0.0),
+        (Pose::Walking, SkinPart::LegRight) => new(30.0, 0.0, 0.0),
+        (Pose::Walking, SkinPart::LegLeft) => new(-30.0, 0.0, 0.0),
+
+        (Pose::Waving, SkinPart::Head) => new(-5.0, 0.0, -6.0),
+        (Pose::Waving, SkinPart::ArmRight) => new(0.0, 0.0, 150.0),
+        (Pose::Waving, SkinPart::ArmLeft) => new(0.0, 0.0, -6.0),
+
+        (Pose::ArmsOut, SkinPart::ArmRight) => new(0.0, 0.0, 90.0),
+        (Pose::ArmsOut, SkinPart::ArmLeft) => new(0.0, 0.0, -90.0),
+
+        _ => PartRotation::default()
+    }
+}
@@ -0,0 +1,134 @@
+use image::imageops::overlay;
+use image::{Rgba, RgbaImage};
+
+use crate::skin_render::{Background, blend_pixel, RenderOptions};
+
+/// the darkest the drop shadow gets, right below the center of the model
+const SHADOW_ALPHA: f64 = 96.0;
+
+/// applies the background, drop shadow and outline of the options to a render.
+/// the returned image can be larger than the render, see post_processed_size
+pub fn post_process(render: RgbaImage, options: &RenderOptions) -> RgbaImage {
+    if options.background == Background::Transparent && !options.drop_shadow && options.outline.is_none() {
+        return render;
+    }
+
+    let (width, height) = post_processed_size(render.width(), render.height(), options);
+    let outline_width = outline_width(options);
+
+    let mut model = RgbaImage::new(width, height);
+    overlay(&mut model, &render, outline_width as i64, outline_width as i64);
+
+    if let Some(color) = options.outline {
+        draw_outline(&mut model, color);
+    }
+
+    let mut result = match options.background {
+        Background::Transparent => RgbaImage::new(width, height),
+        Background::Solid(color) => RgbaImage::from_pixel(width, height, color),
+        Background::Gradient(top, bottom) => vertical_gradient(width, height, top, bottom),
+    };
+
+    if options.drop_shadow {
+        draw_drop_shadow(&mut result, &model);
+    }
+
+    overlay(&mut result, &model, 0, 0);
+    result
+}
+
+/// the size of a render of the given size after post_process
+pub fn post_processed_size(width: u32, height: u32, options: &RenderOptions) -> (u32, u32) {
+    let outline_width = outline_width(options);
+    let shadow_height = if options.drop_shadow { shadow_height(width) / 2 } else { 0 };
+    (width + outline_width * 2, height + outline_width * 2 + shadow_height)
+}
+
+fn outline_width(options: &RenderOptions) -> u32 {
+    if options.outline.is_some() { 1 } else { 0 }
+}
+
+fn shadow_height(width: u32) -> u32 {
+    (width / 6).max(2)
+}
+
+/// colors every transparent pixel next to a visible pixel
+fn draw_outline(image: &mut RgbaImage, color: Rgba<u8>) {
+    let source = image.clone();
+    let (width, height) = source.dimensions();
+
+    for y in 0..height {
+        for x in 0..width {
+            if source.get_pixel(x, y)[3] != 0 {
+                continue;
+            }
+
+            let is_edge = [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|(x_offset, y_offset)| {
+                let neighbour_x = x as i64 + x_offset;
+                let neighbour_y = y as i64 + y_offset;
+                neighbour_x >= 0 && neighbour_y >= 0 && neighbour_x < width as i64 && neighbour_y < height as i64
+                    && source.get_pixel(neighbour_x as u32, neighbour_y as u32)[3] != 0
+            });
+
+            if is_edge {
+                image.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+/// draws an ellipse shaped shadow below the lowest visible pixels of the model
+fn draw_drop_shadow(target: &mut RgbaImage, model: &RgbaImage) {
+    let (width, height) = model.dimensions();
+
+    let lowest_row = (0..height).rev()
+        .find(|&y| (0..width).any(|x| model.get_pixel(x, y)[3] != 0));
+    let Some(lowest_row) = lowest_row else { return };
+
+    // center the shadow below the feet, so it also works for renders that aren't centered
+    let feet_rows = (lowest_row.saturating_sub(height / 10))..=lowest_row;
+    let mut min_x = width;
+    let mut max_x = 0;
+    for y in feet_rows {
+        for x in 0..width {
+            if model.get_pixel(x, y)[3] != 0 {
+                min_x = min_x.min(x);
+                max_x = max_x.max(x);
+            }
+        }
+    }
+
+    let center_x = (min_x + max_x) as f64 / 2.0 + 0.5;
+    let center_y = lowest_row as f64 + 0.5;
+    let radius_x = (width as f64 * 0.35).max((max_x - min_x) as f64 / 2.0 + 1.0);
+    let radius_y = shadow_height(width) as f64 / 2.0;
+
+    for y in 0..target.height() {
+        for x in 0..target.width() {
+            let distance_x = (x as f64 + 0.5 - center_x) / radius_x;
+            let distance_y = (y as f64 + 0.5 - center_y) / radius_y;
+            let distance = distance_x * distance_x + distance_y * distance_y;
+            if distance >= 1.0 {
+                continue;
+            }
+
+            let shadow = [0, 0, 0, (SHADOW_ALPHA * (1.0 - distance)) as u8];
+            blend_pixel(&mut target.get_pixel_mut(x, y).0, &shadow);
+        }
+    }
+}
+
+fn vertical_gradient(width: u32, height: u32, top: Rgba<u8>, bottom: Rgba<u8>) -> RgbaImage {
+    let mut image = RgbaImage::new(width, height);
+    for y in 0..height {
+        let progress = if height > 1 { y as f64 / (height - 1) as f64 } else { 0.0 };
+        let mut color = [0; 4];
+        for (i, channel) in color.iter_mut().enumerate() {
+            *channel = (top[i] as f64 + (bottom[i] as f64 - top[i] as f64) * progress).round() as u8;
+        }
+        for x in 0..width {
+            image.put_pixel(x, y, Rgba(color));
+        }
+    }
+    image
+}
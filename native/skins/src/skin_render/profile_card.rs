@@ -0,0 +1,86 @@
+use image::imageops::{FilterType, overlay, resize};
+use image::{ImageFormat, Rgba, RgbaImage};
+use imageproc::drawing::{draw_text_mut, text_size};
+use lazy_static::lazy_static;
+use rusttype::{Font, Scale};
+
+use crate::common::skin::{SkinLayer, SkinModel};
+use crate::skin_render::flat_render::render_front;
+use crate::skin_render::RenderOptions;
+
+const CARD_WIDTH: u32 = 800;
+const CARD_HEIGHT: u32 = 400;
+const MARGIN: u32 = 40;
+
+const SKIN_RENDER_WIDTH: usize = 160;
+const LOGO_SIZE: u32 = 64;
+const TEXT_X: i32 = 260;
+
+const BACKGROUND_COLOR: Rgba<u8> = Rgba([24, 26, 33, 255]);
+const PRIMARY_TEXT_COLOR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const SECONDARY_TEXT_COLOR: Rgba<u8> = Rgba([168, 172, 184, 255]);
+
+lazy_static! {
+    // the resources are embedded, so that the card doesn't depend on the working directory
+    static ref INTER_BOLD: Font<'static> =
+        Font::try_from_bytes(include_bytes!("../../resources/Inter-Bold.ttf")).expect("invalid Inter-Bold font");
+    static ref INTER_REGULAR: Font<'static> =
+        Font::try_from_bytes(include_bytes!("../../resources/Inter-Regular.ttf")).expect("invalid Inter-Regular font");
+    static ref GEYSER_LOGO: RgbaImage = {
+        let logo = image::load_from_memory_with_format(
+            include_bytes!("../../resources/GeyserMC-logo-192x192.png"), ImageFormat::Png
+        ).expect("invalid GeyserMC logo").to_rgba8();
+        resize(&logo, LOGO_SIZE, LOGO_SIZE, FilterType::Triangle)
+    };
+}
+
+/// a branded card with the front of the skin, the gamertag, the linked Java name and the xuid.
+/// java_name is None when the player isn't linked
+pub fn render_profile_card(
+    skin_data: &[u8],
+    skin_width: usize,
+    model: &SkinModel,
+    gamertag: &str,
+    java_name: Option<&str>,
+    xuid: &str
+) -> RgbaImage {
+    let mut card = RgbaImage::from_pixel(CARD_WIDTH, CARD_HEIGHT, BACKGROUND_COLOR);
+
+    let options = RenderOptions { inflate_overlay: true, ..RenderOptions::default() };
+    let skin = render_front(skin_data, skin_width, &SkinLayer::Both, model, SKIN_RENDER_WIDTH, &options);
+    let skin_y = (CARD_HEIGHT - skin.height()) / 2;
+    overlay(&mut card, &skin, MARGIN as i64, skin_y as i64);
+
+    overlay(&mut card, &*GEYSER_LOGO, (CARD_WIDTH - MARGIN - LOGO_SIZE) as i64, MARGIN as i64);
+
+    let text_width = CARD_WIDTH as i32 - TEXT_X - MARGIN as i32;
+
+    let gamertag_scale = fit_text(&INTER_BOLD, gamertag, 64.0, text_width);
+    draw_text_mut(&mut card, PRIMARY_TEXT_COLOR, TEXT_X, 120, gamertag_scale, &INTER_BOLD, gamertag);
+
+    let java_line = format!("Java: {}", java_name.unwrap_or("not linked"));
+    let java_scale = fit_text(&INTER_REGULAR, &java_line, 32.0, text_width);
+    draw_text_mut(&mut card, SECONDARY_TEXT_COLOR, TEXT_X, 210, java_scale, &INTER_REGULAR, &java_line);
+
+    let xuid_line = format!("XUID: {}", xuid);
+    let xuid_scale = fit_text(&INTER_REGULAR, &xuid_line, 32.0, text_width);
+    draw_text_mut(&mut card, SECONDARY_TEXT_COLOR, TEXT_X, 256, xuid_scale, &INTER_REGULAR, &xuid_line);
+
+    let footer_scale = Scale::uniform(20.0);
+    draw_text_mut(
+        &mut card, SECONDARY_TEXT_COLOR, TEXT_X, (CARD_HEIGHT - MARGIN) as i32 - 20,
+        footer_scale, &INTER_REGULAR, "GeyserMC Global API"
+    );
+
+    card
+}
+
+/// returns the largest scale (up to max_size) at which the text fits within max_width
+fn fit_text(font: &Font, text: &str, max_size: f32, max_width: i32) -> Scale {
+    let scale = Scale::uniform(max_size);
+    let (width, _) = text_size(scale, font, text);
+    if width <= max_width {
+        return scale;
+    }
+    Scale::uniform(max_size * max_width as f32 / width as f32)
+}
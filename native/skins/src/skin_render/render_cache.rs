@@ -0,0 +1,191 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use rustler::{atoms, Encoder, Env, Term};
+use rustler::types::map::map_new;
+use sha2::{Digest, Sha256};
+
+/// the default maximum size of all cached renders combined, can be changed when loading the NIF
+pub const DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+lazy_static! {
+    static ref RENDER_CACHE: Mutex<RenderCache> = Mutex::new(RenderCache::new(DEFAULT_MAX_SIZE));
+}
+
+/// the sha256 of the texture(s), the render type and the options
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheKey([u8; 32]);
+
+impl CacheKey {
+    /// the options should contain everything (besides the texture) that changes the render,
+    /// including something that identifies the kind of render
+    pub fn new(textures: &[&[u8]], options: impl Hash) -> CacheKey {
+        let mut hasher = Sha256::new();
+        for texture in textures {
+            // the length keeps the textures apart, so moving bytes from one texture to the next changes the key
+            hasher.update((texture.len() as u64).to_le_bytes());
+            hasher.update(texture);
+        }
+        // a 64 bit hash of the options could collide and return the render of different options
+        options.hash(&mut Sha256Hasher(&mut hasher));
+
+        CacheKey(hasher.finalize().into())
+    }
+}
+
+/// feeds everything that is hashed into the sha256
+struct Sha256Hasher<'a>(&'a mut Sha256);
+
+impl Hasher for Sha256Hasher<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    /// the hash is read from the sha256 instead
+    fn finish(&self) -> u64 {
+        0
+    }
+}
+
+atoms! {
+    hits,
+    misses,
+    entries,
+    size,
+    max_size,
+}
+
+pub struct RenderCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    /// the size of all cached renders combined, in bytes
+    pub size: usize,
+    pub max_size: usize,
+}
+
+impl Encoder for RenderCacheStats {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        map_new(env)
+            .map_put(hits().encode(env), self.hits.encode(env)).unwrap()
+            .map_put(misses().encode(env), self.misses.encode(env)).unwrap()
+            .map_put(entries().encode(env), self.entries.encode(env)).unwrap()
+            .map_put(size().encode(env), self.size.encode(env)).unwrap()
+            .map_put(max_size().encode(env), self.max_size.encode(env)).unwrap()
+    }
+}
+
+/// a least recently used cache of encoded renders that is limited by the size of the renders
+struct RenderCache {
+    max_size: usize,
+    size: usize,
+    /// the render and the last time it was used
+    entries: HashMap<CacheKey, (Vec<u8>, u64)>,
+    /// the keys ordered by the last time they were used
+    usage: BTreeMap<u64, CacheKey>,
+    time: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl RenderCache {
+    fn new(max_size: usize) -> RenderCache {
+        RenderCache {
+            max_size,
+            size: 0,
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            time: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    fn get(&mut self, key: &CacheKey) -> Option<Vec<u8>> {
+        self.time += 1;
+        let Some((render, last_used)) = self.entries.get_mut(key) else {
+            self.misses += 1;
+            return None;
+        };
+
+        self.usage.remove(last_used);
+        self.usage.insert(self.time, *key);
+        *last_used = self.time;
+
+        self.hits += 1;
+        Some(render.clone())
+    }
+
+    fn insert(&mut self, key: CacheKey, render: Vec<u8>) {
+        if render.len() > self.max_size || self.entries.contains_key(&key) {
+            return;
+        }
+
+        self.evict_until(self.max_size - render.len());
+
+        self.time += 1;
+        self.size += render.len();
+        self.usage.insert(self.time, key);
+        self.entries.insert(key, (render, self.time));
+    }
+
+    fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict_until(max_size);
+    }
+
+    /// removes the least recently used renders until the cache isn't larger than the given size
+    fn evict_until(&mut self, size: usize) {
+        while self.size > size {
+            let Some((_, oldest)) = self.usage.pop_first() else { break };
+            if let Some((removed, _)) = self.entries.remove(&oldest) {
+                self.size -= removed.len();
+            }
+        }
+    }
+}
+
+/// returns the cached render, or renders it and caches the result when it was successful
+pub fn get_or_render<E>(key: CacheKey, render: impl FnOnce() -> Result<Vec<u8>, E>) -> Result<Vec<u8>, E> {
+    if let Some(cached) = RENDER_CACHE.lock().unwrap().get(&key) {
+        return Ok(cached);
+    }
+
+    // the lock isn't held while rendering, so other renders don't have to wait
+    let rendered = render()?;
+    RENDER_CACHE.lock().unwrap().insert(key, rendered.clone());
+    Ok(rendered)
+}
+
+/// changes the maximum size of all cached renders combined, 0 disables the cache
+pub fn set_max_size(max_size: usize) {
+    RENDER_CACHE.lock().unwrap().set_max_size(max_size);
+}
+
+pub fn stats() -> RenderCacheStats {
+    let cache = RENDER_CACHE.lock().unwrap();
+    RenderCacheStats {
+        hits: cache.hits,
+        misses: cache.misses,
+        entries: cache.entries.len(),
+        size: cache.size,
+        max_size: cache.max_size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_on_textures_and_options() {
+        let key = CacheKey::new(&[b"skin", b"cape"], ("back", 64));
+        assert!(key == CacheKey::new(&[b"skin", b"cape"], ("back", 64)));
+
+        assert!(key != CacheKey::new(&[b"skin", b"cape"], ("back", 128)));
+        assert!(key != CacheKey::new(&[b"skin", b"cape"], ("front", 64)));
+        assert!(key != CacheKey::new(&[b"skinc", b"ape"], ("back", 64)));
+    }
+}
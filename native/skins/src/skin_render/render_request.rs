@@ -0,0 +1,357 @@
+use image::RgbaImage;
+use rustler::{Atom, atoms, Binary, Decoder, Error, NifResult, Term};
+use rustler::types::tuple::get_tuple;
+
+use crate::common::cape::CapePart;
+use crate::common::encoder::{encode_animation, encode_image, EncodeError};
+use crate::common::RGBA_CHANNELS;
+use crate::common::skin::{SkinLayer, SkinModel};
+use crate::common::texture_decoder::{decode_png, decode_skin, DecodeError};
+use crate::skin_render::{animated_face, cape_render, geometry_render, MAX_TARGET_WIDTH, RenderOptions};
+use crate::skin_render::cape_render::render_cape_on_back;
+use crate::skin_render::flat_render::{render_back, render_front};
+use crate::skin_render::isometric_render::render_isometric;
+use crate::skin_render::pose::Pose;
+use crate::skin_render::post_process::post_process;
+use crate::skin_render::render_cache::{CacheKey, get_or_render};
+
+atoms! {
+    front,
+    back,
+    isometric,
+    cape,
+    bedrock,
+    animated_face,
+}
+
+/// a single render, the data of every request is the (png) texture unless noted otherwise.
+/// Requests don't hold any terms, so they can be rendered outside the calling thread
+pub enum RenderRequest<'a> {
+    Front {
+        data: &'a [u8],
+        layer: SkinLayer,
+        model: SkinModel,
+        target_width: usize,
+        options: RenderOptions,
+    },
+    Back {
+        data: &'a [u8],
+        cape_data: Option<&'a [u8]>,
+        layer: SkinLayer,
+        model: SkinModel,
+        target_width: usize,
+        options: RenderOptions,
+    },
+    Isometric {
+        data: &'a [u8],
+        layer: SkinLayer,
+        model: SkinModel,
+        pose: Pose,
+        target_width: usize,
+        options: RenderOptions,
+    },
+    Cape {
+        data: &'a [u8],
+        part: CapePart,
+        target_width: usize,
+        options: RenderOptions,
+    },
+    /// the skin data is the raw (RGBA) skin data, the geometry data and resource patch are json
+    Bedrock {
+        skin_data: &'a [u8],
+        width: usize,
+        height: usize,
+        geometry_data: &'a [u8],
+        resource_patch: &'a [u8],
+        layer: SkinLayer,
+        target_width: usize,
+        options: RenderOptions,
+    },
+    AnimatedFace {
+        data: &'a [u8],
+        frame_count: usize,
+        frame_delay_ms: u16,
+        layer: SkinLayer,
+        target_width: usize,
+        options: RenderOptions,
+    },
+}
+
+#[allow(clippy::enum_variant_names)] // named after the atoms they're returned as
+pub enum RenderError {
+    InvalidTexture(DecodeError),
+    InvalidSize,
+    InvalidGeometry(&'static str),
+    UnsupportedFormat,
+}
+
+impl<'a> RenderRequest<'a> {
+    /// renders and encodes the request, or returns the render from the render cache
+    pub fn render(&self) -> Result<Vec<u8>, RenderError> {
+        if self.target_width() > MAX_TARGET_WIDTH {
+            return Err(RenderError::InvalidSize);
+        }
+        get_or_render(self.cache_key(), || self.render_uncached())
+    }
+
+    fn render_uncached(&self) -> Result<Vec<u8>, RenderError> {
+        match self {
+            RenderRequest::Front { data, layer, model, target_width, options } => {
+                let skin = decode_skin(data)?;
+                let render = render_front(&skin.data, skin.width, layer, model, *target_width, options);
+                encode_render(render, options)
+            }
+            RenderRequest::Back { data, cape_data, layer, model, target_width, options } => {
+                let skin = decode_skin(data)?;
+                let mut render = render_back(&skin.data, skin.width, layer, model, *target_width, options);
+
+                if let Some(cape_data) = cape_data {
+                    let cape = decode_png(cape_data)?;
+                    render_cape_on_back(&mut render, &cape.data, cape.width, layer, *target_width, options);
+                }
+
+                encode_render(render, options)
+            }
+            RenderRequest::Isometric { data, layer, model, pose, target_width, options } => {
+                let skin = decode_skin(data)?;
+                let render = render_isometric(&skin.data, skin.width, layer, model, pose, *target_width, options);
+                encode_render(render, options)
+            }
+            RenderRequest::Cape { data, part, target_width, options } => {
+                let cape = decode_png(data)?;
+                let render = cape_render::render_cape(&cape.data, cape.width, part, *target_width);
+                encode_render(render, options)
+            }
+            RenderRequest::Bedrock {
+                skin_data, width, height, geometry_data, resource_patch, layer, target_width, options
+            } => {
+                // the dimensions are given by the caller, so a wrapped product could match the length
+                let size = width.checked_mul(*height).and_then(|pixels| pixels.checked_mul(RGBA_CHANNELS));
+                if *width == 0 || size != Some(skin_data.len()) {
+                    return Err(RenderError::InvalidSize);
+                }
+
+                let render = geometry_render::render_geometry(
+                    skin_data, *width, geometry_data, resource_patch, layer, *target_width, options
+                ).map_err(RenderError::InvalidGeometry)?;
+                encode_render(render, options)
+            }
+            RenderRequest::AnimatedFace { data, frame_count, frame_delay_ms, layer, target_width, options } => {
+                let texture = decode_png(data)?;
+                let frames = animated_face::render_animated_face(
+                    &texture.data, texture.width, *frame_count, layer, *target_width, options
+                ).ok_or(RenderError::InvalidSize)?;
+                if frames.iter().any(|frame| frame.width() == 0 || frame.height() == 0) {
+                    return Err(RenderError::InvalidSize);
+                }
+
+                let frames: Vec<RgbaImage> = frames.into_iter()
+                    .map(|frame| post_process(frame, options))
+                    .collect();
+                Ok(encode_animation(&frames, *frame_delay_ms, options.format)?)
+            }
+        }
+    }
+
+    fn target_width(&self) -> usize {
+        match self {
+            RenderRequest::Front { target_width, .. }
+            | RenderRequest::Back { target_width, .. }
+            | RenderRequest::Isometric { target_width, .. }
+            | RenderRequest::Cape { target_width, .. }
+            | RenderRequest::Bedrock { target_width, .. }
+            | RenderRequest::AnimatedFace { target_width, .. } => *target_width,
+        }
+    }
+
+    fn cache_key(&self) -> CacheKey {
+        match self {
+            RenderRequest::Front { data, layer, model, target_width, options } =>
+                CacheKey::new(&[data], ("front", layer, model, target_width, options)),
+            RenderRequest::Back { data, cape_data, layer, model, target_width, options } =>
+                CacheKey::new(
+                    &[data, cape_data.unwrap_or_default()],
+                    ("back", cape_data.is_some(), layer, model, target_width, options)
+                ),
+            RenderRequest::Isometric { data, layer, model, pose, target_width, options } =>
+                CacheKey::new(&[data], ("isometric", layer, model, pose, target_width, options)),
+            RenderRequest::Cape { data, part, target_width, options } =>
+                CacheKey::new(&[data], ("cape", part, target_width, options)),
+            RenderRequest::Bedrock {
+                skin_data, width, height, geometry_data, resource_patch, layer, target_width, options
+            } => CacheKey::new(
+                &[skin_data, geometry_data, resource_patch],
+                ("bedrock", width, height, geometry_data.len(), layer, target_width, options)
+            ),
+            RenderRequest::AnimatedFace { data, frame_count, frame_delay_ms, layer, target_width, options } =>
+                CacheKey::new(
+                    &[data],
+                    ("animated_face", frame_count, frame_delay_ms, layer, target_width, options)
+                ),
+        }
+    }
+}
+
+/// post processes and encodes a single render.
+/// Target widths that are too small for the render give an empty image, which can't be encoded
+fn encode_render(render: RgbaImage, options: &RenderOptions) -> Result<Vec<u8>, RenderError> {
+    if render.width() == 0 || render.height() == 0 {
+        return Err(RenderError::InvalidSize);
+    }
+    Ok(encode_image(&post_process(render, options), options.format)?)
+}
+
+impl From<EncodeError> for RenderError {
+    fn from(err: EncodeError) -> Self {
+        match err {
+            EncodeError::InvalidImage => RenderError::InvalidSize,
+            EncodeError::UnsupportedAnimation => RenderError::UnsupportedFormat,
+        }
+    }
+}
+
+impl From<DecodeError> for RenderError {
+    fn from(err: DecodeError) -> Self {
+        RenderError::InvalidTexture(err)
+    }
+}
+
+/// requests are tuples with the render type as first element,
+/// followed by the arguments of the NIF that does the same render
+impl<'a> Decoder<'a> for RenderRequest<'a> {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let elements = get_tuple(term)?;
+        let Some((render_type, args)) = elements.split_first() else { return Err(Error::BadArg) };
+        let render_type: Atom = render_type.decode()?;
+
+        if render_type == front() {
+            if args.len() != 5 {
+                return Err(Error::BadArg);
+            }
+            return Ok(RenderRequest::Front {
+                data: args[0].decode::<Binary>()?.as_slice(),
+                layer: args[1].decode()?,
+                model: args[2].decode()?,
+                target_width: args[3].decode()?,
+                options: args[4].decode()?,
+            });
+        }
+        if render_type == back() {
+            if args.len() != 6 {
+                return Err(Error::BadArg);
+            }
+            return Ok(RenderRequest::Back {
+                data: args[0].decode::<Binary>()?.as_slice(),
+                cape_data: args[1].decode::<Option<Binary>>()?.map(|cape_data| cape_data.as_slice()),
+                layer: args[2].decode()?,
+                model: args[3].decode()?,
+                target_width: args[4].decode()?,
+                options: args[5].decode()?,
+            });
+        }
+        if render_type == isometric() {
+            if args.len() != 6 {
+                return Err(Error::BadArg);
+            }
+            return Ok(RenderRequest::Isometric {
+                data: args[0].decode::<Binary>()?.as_slice(),
+                layer: args[1].decode()?,
+                model: args[2].decode()?,
+                pose: args[3].decode()?,
+                target_width: args[4].decode()?,
+                options: args[5].decode()?,
+            });
+        }
+        if render_type == cape() {
+            if args.len() != 4 {
+                return Err(Error::BadArg);
+            }
+            return Ok(RenderRequest::Cape {
+                data: args[0].decode::<Binary>()?.as_slice(),
+                part: args[1].decode()?,
+                target_width: args[2].decode()?,
+                options: args[3].decode()?,
+            });
+        }
+        if render_type == bedrock() {
+            if args.len() != 8 {
+                return Err(Error::BadArg);
+            }
+            return Ok(RenderRequest::Bedrock {
+                skin_data: args[0].decode::<Binary>()?.as_slice(),
+                width: args[1].decode()?,
+                height: args[2].decode()?,
+                geometry_data: args[3].decode::<Binary>()?.as_slice(),
+                resource_patch: args[4].decode::<Binary>()?.as_slice(),
+                layer: args[5].decode()?,
+                target_width: args[6].decode()?,
+                options: args[7].decode()?,
+            });
+        }
+        if render_type == animated_face() {
+            if args.len() != 6 {
+                return Err(Error::BadArg);
+            }
+            return Ok(RenderRequest::AnimatedFace {
+                data: args[0].decode::<Binary>()?.as_slice(),
+                frame_count: args[1].decode()?,
+                frame_delay_ms: args[2].decode()?,
+                layer: args[3].decode()?,
+                target_width: args[4].decode()?,
+                options: args[5].decode()?,
+            });
+        }
+        Err(Error::BadArg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::{SKIN_HEIGHT, SKIN_WIDTH};
+    use crate::common::encoder::encode_minecraft_png;
+    use crate::common::skin::STEVE_SKIN;
+
+    use super::*;
+
+    fn requests<'a>(skin: &'a [u8], cape: &'a [u8], target_width: usize) -> Vec<RenderRequest<'a>> {
+        vec![
+            RenderRequest::Front {
+                data: skin, layer: SkinLayer::Both, model: SkinModel::Classic, target_width, options: RenderOptions::default()
+            },
+            RenderRequest::Back {
+                data: skin, cape_data: Some(cape), layer: SkinLayer::Both, model: SkinModel::Classic,
+                target_width, options: RenderOptions::default()
+            },
+            RenderRequest::Cape { data: cape, part: CapePart::Cape, target_width, options: RenderOptions::default() },
+            RenderRequest::AnimatedFace {
+                data: skin, frame_count: 1, frame_delay_ms: 100, layer: SkinLayer::Both,
+                target_width, options: RenderOptions::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn rejects_invalid_target_widths() {
+        let skin = encode_minecraft_png(&STEVE_SKIN, SKIN_WIDTH, SKIN_HEIGHT);
+        let cape = encode_minecraft_png(&[255; 64 * 32 * RGBA_CHANNELS], 64, 32);
+
+        for target_width in [0, 1, 7, MAX_TARGET_WIDTH + 1] {
+            for request in requests(&skin, &cape, target_width) {
+                assert!(matches!(request.render(), Err(RenderError::InvalidSize)), "target width {}", target_width);
+            }
+        }
+        for request in requests(&skin, &cape, 64) {
+            assert!(request.render().is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_bedrock_dimensions() {
+        // 2^62 * 4 * 4 wraps around to 0, the length of the skin data
+        let request = RenderRequest::Bedrock {
+            skin_data: &[], width: 1 << 62, height: 4, geometry_data: b"{}", resource_patch: b"{}",
+            layer: SkinLayer::Both, target_width: 64, options: RenderOptions::default()
+        };
+        assert!(matches!(request.render(), Err(RenderError::InvalidSize)));
+    }
+}
@@ -0,0 +1,101 @@
+use image::imageops::replace;
+use image::RgbaImage;
+
+use crate::common::OffsetAndDimension;
+use crate::common::skin::{SkinLayer, SkinModel};
+use crate::skin_render::flat_render::{flat_render_padding, flat_render_scale, render_front};
+use crate::skin_render::post_process::{post_process, post_processed_size};
+use crate::skin_render::RenderOptions;
+
+/// the max amount of skins on a single sprite sheet
+pub const MAX_SPRITE_SKINS: usize = 256;
+/// the max amount of pixels of a sprite sheet, which takes 64MB
+pub const MAX_SHEET_PIXELS: usize = 4096 * 4096;
+
+/// a skin that should be placed on the sprite sheet, None if the skin couldn't be decoded
+pub type SpriteSkin<'a> = Option<(&'a [u8], usize, &'a SkinModel)>;
+
+/// renders the front of every skin and places them in a grid with the given amount of columns.
+/// returns the sheet and the position of every skin on the sheet,
+/// in the same order as the given skins. Skins that are None will have no position.
+/// None when there are more than MAX_SPRITE_SKINS skins or the sheet would be larger than MAX_SHEET_PIXELS
+pub fn render_sprite_sheet(
+    skins: &[SpriteSkin],
+    columns: usize,
+    layer: &SkinLayer,
+    target_width: usize,
+    options: &RenderOptions
+) -> Option<(RgbaImage, Vec<Option<OffsetAndDimension>>)> {
+    if skins.len() > MAX_SPRITE_SKINS {
+        return None;
+    }
+
+    // more columns than skins would only add empty space
+    let columns = columns.clamp(1, skins.len().max(1));
+    let rows = skins.len().div_ceil(columns);
+
+    // every render has the same size, so every cell has the same size as well
+    let scale = flat_render_scale(target_width);
+    let padding = flat_render_padding(scale, layer, options);
+    let (cell_width, cell_height) = post_processed_size(
+        (16 * scale + padding * 2) as u32, (32 * scale + padding * 2) as u32, options
+    );
+    let cell_width = cell_width as usize;
+    let cell_height = cell_height as usize;
+
+    let sheet_width = cell_width.checked_mul(columns)?;
+    let sheet_height = cell_height.checked_mul(rows)?;
+    if sheet_width.checked_mul(sheet_height)? > MAX_SHEET_PIXELS {
+        return None;
+    }
+
+    let mut sheet = RgbaImage::new(sheet_width as u32, sheet_height as u32);
+    let mut manifest = Vec::with_capacity(skins.len());
+
+    for (index, skin) in skins.iter().enumerate() {
+        if let Some((data, data_width, model)) = skin {
+            let x_offset = (index % columns) * cell_width;
+            let y_offset = (index / columns) * cell_height;
+
+            let render = render_front(data, *data_width, layer, model, target_width, options);
+            let render = post_process(render, options);
+            replace(&mut sheet, &render, x_offset as i64, y_offset as i64);
+
+            manifest.push(Some(OffsetAndDimension::new(x_offset, y_offset, cell_width, cell_height)));
+        } else {
+            manifest.push(None);
+        }
+    }
+
+    Some((sheet, manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::skin::STEVE_SKIN;
+
+    use super::*;
+
+    #[test]
+    fn places_skins_in_grid() {
+        let skin: SpriteSkin = Some((&STEVE_SKIN, 64, &SkinModel::Classic));
+        let (sheet, manifest) =
+            render_sprite_sheet(&[skin, None, skin], 2, &SkinLayer::Both, 32, &RenderOptions::default()).unwrap();
+
+        let first = manifest[0].as_ref().unwrap();
+        assert_eq!((sheet.width() as usize, sheet.height() as usize), (first.width * 2, first.height * 2));
+        assert!(manifest[1].is_none());
+        assert_eq!(manifest[2].as_ref().unwrap().y_offset, first.height);
+    }
+
+    #[test]
+    fn rejects_too_large_sheets() {
+        let options = RenderOptions::default();
+        let skins = vec![None; MAX_SPRITE_SKINS + 1];
+        assert!(render_sprite_sheet(&skins, 16, &SkinLayer::Both, 32, &options).is_none());
+
+        // every cell of a 2048 pixels wide render is 2048x4096
+        let skins = vec![None; 4];
+        assert!(render_sprite_sheet(&skins, 4, &SkinLayer::Both, 2048, &options).is_none());
+    }
+}
@@ -0,0 +1,14 @@
+defmodule GlobalApi.Repo.Migrations.AddHashVersionToUniqueSkins do
+  use Ecto.Migration
+
+  def change do
+    # every existing unique skin has been hashed with the first (raw) hash version
+    alter table(:unique_skins) do
+      add :hash_version, :smallint, null: false, default: 1
+    end
+
+    # the same hash can belong to a different skin in another hash version
+    drop(unique_index("unique_skins", [:hash, :is_steve], name: "unique_skin"))
+    create(unique_index("unique_skins", [:hash, :is_steve, :hash_version], name: "unique_skin"))
+  end
+end